@@ -8,7 +8,7 @@ fn main() {
     let mut cipher = ChaCha20Impl::new(&key, &nonce);
 
     let plaintext = b"Hello, ChaCha20!"; // Example plaintext
-    let mut ciphertext = plaintext.clone();
+    let mut ciphertext = *plaintext;
 
     // Encrypt the plaintext
     cipher.apply_keystream(&mut ciphertext);
@@ -16,7 +16,7 @@ fn main() {
 
     // Decrypt the ciphertext
     cipher.seek(0); // Reset the counter
-    let mut decrypted = ciphertext.clone();
+    let mut decrypted = ciphertext;
     cipher.apply_keystream(&mut decrypted);
 
     println!("Decrypted: {:?}", String::from_utf8_lossy(&decrypted));
@@ -1,13 +1,12 @@
-use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+use chacha20imp::stream::{OpeningReader, SealingWriter, StreamDecryptor, StreamEncryptor};
 use rand::Rng;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::{self, Write};
 
-fn main() -> std::io::Result<()> {
+fn main() -> io::Result<()> {
     let key: [u8; 32] = rand::thread_rng().gen();
-    let nonce: [u8; 12] = rand::thread_rng().gen();
-
-    let mut cipher = ChaCha20Impl::new(&key, &nonce);
+    // The nonce prefix must be unique per key; it can be stored next to the ciphertext
+    let prefix: [u8; 7] = rand::thread_rng().gen();
 
     // For this example we will create a file first
     //
@@ -17,35 +16,18 @@ fn main() -> std::io::Result<()> {
     writeln!(input_file, "This is a sample text to demonstrate ChaCha20 encryption!")?;
     println!("Created 'example.txt' with sample content.");
 
-    // Step 2: Encrypt the content of example.txt
+    // Step 2: Encrypt the content of example.txt into authenticated segments
     let mut input_file = File::open("example.txt")?;
-    let mut output_file = OpenOptions::new().write(true).create(true).open("example.enc")?;
-
-    let mut buffer = [0u8; 64];
-    while let Ok(bytes_read) = input_file.read(&mut buffer) {
-        if bytes_read == 0 {
-            break;
-        }
-
-        cipher.apply_keystream(&mut buffer[..bytes_read]);
-        output_file.write_all(&buffer[..bytes_read])?;
-    }
+    let mut writer = SealingWriter::new(File::create("example.enc")?, StreamEncryptor::new(&key, &prefix));
+    io::copy(&mut input_file, &mut writer)?;
+    writer.finish()?;
 
     println!("File encrypted successfully! Output: 'example.enc'");
 
-    // Step 3: Decrypt the content of example.enc
-    cipher.seek(0); // Reset the counter
-    let mut encrypted_file = File::open("example.enc")?;
-    let mut decrypted_file = OpenOptions::new().write(true).create(true).open("example_dec.txt")?;
-
-    while let Ok(bytes_read) = encrypted_file.read(&mut buffer) {
-        if bytes_read == 0 {
-            break;
-        }
-
-        cipher.apply_keystream(&mut buffer[..bytes_read]);
-        decrypted_file.write_all(&buffer[..bytes_read])?;
-    }
+    // Step 3: Decrypt the content of example.enc, failing if it was truncated or modified
+    let mut reader = OpeningReader::new(File::open("example.enc")?, StreamDecryptor::new(&key, &prefix));
+    let mut decrypted_file = File::create("example_dec.txt")?;
+    io::copy(&mut reader, &mut decrypted_file)?;
 
     println!("File decrypted successfully! Output: 'example_dec.txt'");
     Ok(())
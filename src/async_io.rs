@@ -0,0 +1,207 @@
+//! # Async reader and writer adapters
+//!
+//! The async counterparts of [`crate::io`]: [`AsyncEncryptingWriter`] encrypts what is written to
+//! it before handing it to the inner writer and [`AsyncDecryptingReader`] decrypts what it reads
+//! from the inner reader. They implement the tokio traits with the `tokio` feature and the
+//! `futures-io` traits with the `futures-io` feature.
+//!
+//! A write that returns `Pending`, fails or is only partially accepted by the inner writer moves
+//! the cipher back to the first byte that was not written, and a read only decrypts the bytes
+//! actually read. The keystream position therefore always matches the bytes that went through,
+//! however the inner stream splits them up.
+//!
+//! The inner reader or writer must be `Unpin`; wrap it in `Box::pin` if it is not.
+//!
+//! ## Example
+//! ```rust
+//! # #[cfg(feature = "tokio")]
+//! # async fn example() -> std::io::Result<()> {
+//! use chacha20imp::async_io::{AsyncDecryptingReader, AsyncEncryptingWriter};
+//! use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! let key = [0x42u8; 32];
+//! let nonce = [0x24u8; 12];
+//! let (client, server) = tokio::io::duplex(64);
+//!
+//! let mut writer = AsyncEncryptingWriter::new(client, ChaCha20Impl::new(&key, &nonce));
+//! writer.write_all(b"Hello, world!").await?;
+//! writer.shutdown().await?;
+//!
+//! let mut reader = AsyncDecryptingReader::new(server, ChaCha20Impl::new(&key, &nonce));
+//! let mut plaintext = String::new();
+//! reader.read_to_string(&mut plaintext).await?;
+//! assert_eq!(plaintext, "Hello, world!");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::chacha20::{ChaCha20, ChaCha20Impl};
+use crate::io::{decrypt, keystream_error, CHUNK_SIZE};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::io;
+
+/// Encrypt up to [`CHUNK_SIZE`] bytes of `buf` and hand them to `write`
+///
+/// The cipher is moved back to the first byte the inner writer did not take, including when it
+/// returns `Pending` or an error.
+fn poll_write_encrypted<C: ChaCha20>(
+    cipher: &mut C,
+    buf: &[u8],
+    write: impl FnOnce(&[u8]) -> Poll<io::Result<usize>>,
+) -> Poll<io::Result<usize>> {
+    if buf.is_empty() {
+        return Poll::Ready(Ok(0));
+    }
+
+    let len = buf.len().min(CHUNK_SIZE);
+    let mut chunk = [0u8; CHUNK_SIZE];
+    chunk[..len].copy_from_slice(&buf[..len]);
+
+    let pos = cipher.current_pos();
+    if let Err(error) = cipher.try_apply_keystream(&mut chunk[..len]) {
+        return Poll::Ready(Err(keystream_error(error)));
+    }
+
+    let result = write(&chunk[..len]);
+    let written = match result {
+        Poll::Ready(Ok(written)) => written,
+        _ => 0,
+    };
+    if written < len {
+        if let Err(error) = cipher.seek_pos(pos + written as u64) {
+            return Poll::Ready(Err(keystream_error(error)));
+        }
+    }
+
+    result
+}
+
+/// Async writer that encrypts data before writing it to the inner writer
+pub struct AsyncEncryptingWriter<W, C: ChaCha20 = ChaCha20Impl> {
+    inner: W,
+    cipher: C,
+}
+
+impl<W, C: ChaCha20> AsyncEncryptingWriter<W, C> {
+    /// Wrap `inner`, encrypting with `cipher` from its current position
+    pub fn new(inner: W, cipher: C) -> Self {
+        AsyncEncryptingWriter { inner, cipher }
+    }
+
+    /// Get a reference to the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner writer
+    ///
+    /// Writing to it directly desynchronises the ciphertext from the keystream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Get a reference to the cipher
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    /// Unwrap the inner writer
+    ///
+    /// Nothing is buffered, so every byte accepted by a write has already reached it.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Async reader that decrypts data read from the inner reader
+pub struct AsyncDecryptingReader<R, C: ChaCha20 = ChaCha20Impl> {
+    inner: R,
+    cipher: C,
+}
+
+impl<R, C: ChaCha20> AsyncDecryptingReader<R, C> {
+    /// Wrap `inner`, decrypting with `cipher` from its current position
+    pub fn new(inner: R, cipher: C) -> Self {
+        AsyncDecryptingReader { inner, cipher }
+    }
+
+    /// Get a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader
+    ///
+    /// Reading from it directly desynchronises the ciphertext from the keystream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get a reference to the cipher
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin, C: ChaCha20 + Unpin> tokio::io::AsyncWrite for AsyncEncryptingWriter<W, C> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        poll_write_encrypted(&mut this.cipher, buf, |chunk| Pin::new(&mut this.inner).poll_write(cx, chunk))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncRead + Unpin, C: ChaCha20 + Unpin> tokio::io::AsyncRead for AsyncDecryptingReader<R, C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        core::task::ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        Poll::Ready(decrypt(&mut this.cipher, &mut buf.filled_mut()[filled..]))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<W: futures_io::AsyncWrite + Unpin, C: ChaCha20 + Unpin> futures_io::AsyncWrite for AsyncEncryptingWriter<W, C> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        poll_write_encrypted(&mut this.cipher, buf, |chunk| Pin::new(&mut this.inner).poll_write(cx, chunk))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<R: futures_io::AsyncRead + Unpin, C: ChaCha20 + Unpin> futures_io::AsyncRead for AsyncDecryptingReader<R, C> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = core::task::ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        decrypt(&mut this.cipher, &mut buf[..read])?;
+        Poll::Ready(Ok(read))
+    }
+}
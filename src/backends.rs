@@ -0,0 +1,86 @@
+//! # Keystream backends
+//!
+//! ChaCha20 blocks are independent of each other, so several of them can be computed at once with
+//! SIMD instructions. On x86 and x86_64 the SSE2 backend computes 4 blocks in parallel and the
+//! AVX2 backend 8 blocks. [`Backend::detect`] picks the fastest backend the CPU supports, asking
+//! CPUID at runtime through `cpufeatures`, which needs neither `std` nor compile-time target
+//! features. The portable scalar backend is always available and interleaves 4 blocks, which also
+//! speeds up targets without a dedicated backend such as aarch64 and wasm32.
+//!
+//! Every backend produces the same keystream; only the speed differs.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::backends::Backend;
+//! use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+//!
+//! let mut cipher = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+//! cipher.set_backend(Backend::Soft);
+//! let mut buf = [0u8; 1024];
+//! cipher.apply_keystream(&mut buf);
+//! ```
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) mod avx2;
+pub(crate) mod soft;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) mod sse2;
+
+/// Implementation used to generate the keystream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Portable scalar code, 4 blocks at a time
+    Soft,
+    /// 4 blocks in parallel with SSE2 (x86 and x86_64 only)
+    Sse2,
+    /// 8 blocks in parallel with AVX2 (x86 and x86_64 only)
+    Avx2,
+}
+
+// Each `get()` runs CPUID once and caches the answer
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+cpufeatures::new!(sse2_cpuid, "sse2");
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+cpufeatures::new!(avx2_cpuid, "avx2");
+
+impl Backend {
+    /// Every backend, from the slowest to the fastest
+    pub const ALL: [Backend; 3] = [Backend::Soft, Backend::Sse2, Backend::Avx2];
+
+    /// Get the fastest backend supported by the current CPU
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::backends::Backend;
+    /// assert!(Backend::detect().is_available());
+    /// ```
+    pub fn detect() -> Backend {
+        Backend::ALL
+            .into_iter()
+            .rev()
+            .find(|backend| backend.is_available())
+            .unwrap_or(Backend::Soft)
+    }
+
+    /// Check whether the backend can run on the current CPU
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Soft => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2 => sse2_cpuid::get(),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx2 => avx2_cpuid::get(),
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            Backend::Sse2 | Backend::Avx2 => false,
+        }
+    }
+
+    /// Number of 64-byte blocks computed per call
+    pub fn parallel_blocks(self) -> usize {
+        match self {
+            Backend::Soft => 4,
+            Backend::Sse2 => 4,
+            Backend::Avx2 => 8,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! 8-way ChaCha with AVX2
+//!
+//! Same layout as the SSE2 backend with 8 blocks per vector. AVX2 shuffles each 128-bit half
+//! independently, so after the transpose the low half of a row belongs to block `n` and the high
+//! half to block `n + 4`.
+
+use crate::wipe;
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+/// Number of blocks generated per call
+pub(crate) const BLOCKS: usize = 8;
+
+/// Generate 8 consecutive keystream blocks and advance the block counter in `state[12]` by 8
+///
+/// # Safety
+/// The CPU must support AVX2.
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn keystream_blocks(state: &mut [u32; 16], double_rounds: usize, output: &mut [u8; 64 * BLOCKS]) {
+    let mut input = [_mm256_setzero_si256(); 16];
+    for (v, word) in input.iter_mut().zip(state.iter()) {
+        *v = _mm256_set1_epi32(*word as i32);
+    }
+    input[12] = _mm256_add_epi32(input[12], _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7));
+
+    let mut v = input;
+    for _ in 0..double_rounds {
+        // column rounds
+        quarter_round(&mut v, 0, 4, 8, 12);
+        quarter_round(&mut v, 1, 5, 9, 13);
+        quarter_round(&mut v, 2, 6, 10, 14);
+        quarter_round(&mut v, 3, 7, 11, 15);
+
+        // diagonal rounds
+        quarter_round(&mut v, 0, 5, 10, 15);
+        quarter_round(&mut v, 1, 6, 11, 12);
+        quarter_round(&mut v, 2, 7, 8, 13);
+        quarter_round(&mut v, 3, 4, 9, 14);
+    }
+
+    for (x, i) in v.iter_mut().zip(input.iter()) {
+        *x = _mm256_add_epi32(*x, *i);
+    }
+
+    // Transpose each group of 4 words so that every 128-bit half holds 16 bytes of a single block
+    let out = output.as_mut_ptr();
+    for group in 0..4 {
+        let [a, b, c, d] = [v[4 * group], v[4 * group + 1], v[4 * group + 2], v[4 * group + 3]];
+        let ab_lo = _mm256_unpacklo_epi32(a, b);
+        let cd_lo = _mm256_unpacklo_epi32(c, d);
+        let ab_hi = _mm256_unpackhi_epi32(a, b);
+        let cd_hi = _mm256_unpackhi_epi32(c, d);
+
+        let rows = [
+            _mm256_unpacklo_epi64(ab_lo, cd_lo),
+            _mm256_unpackhi_epi64(ab_lo, cd_lo),
+            _mm256_unpacklo_epi64(ab_hi, cd_hi),
+            _mm256_unpackhi_epi64(ab_hi, cd_hi),
+        ];
+        for (block, row) in rows.iter().enumerate() {
+            let low = _mm256_castsi256_si128(*row);
+            let high = _mm256_extracti128_si256::<1>(*row);
+            _mm_storeu_si128(out.add(64 * block + 16 * group) as *mut __m128i, low);
+            _mm_storeu_si128(out.add(64 * (block + 4) + 16 * group) as *mut __m128i, high);
+        }
+    }
+
+    state[12] = state[12].wrapping_add(BLOCKS as u32);
+
+    wipe(&mut input);
+    wipe(&mut v);
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn quarter_round(v: &mut [__m256i; 16], a: usize, b: usize, c: usize, d: usize) {
+    // Byte shuffles rotating every 32-bit lane left by 16 and 8 bits
+    let rotate_16 = _mm256_setr_epi8(
+        2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13,
+        2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13,
+    );
+    let rotate_8 = _mm256_setr_epi8(
+        3, 0, 1, 2, 7, 4, 5, 6, 11, 8, 9, 10, 15, 12, 13, 14,
+        3, 0, 1, 2, 7, 4, 5, 6, 11, 8, 9, 10, 15, 12, 13, 14,
+    );
+
+    v[a] = _mm256_add_epi32(v[a], v[b]);
+    v[d] = _mm256_shuffle_epi8(_mm256_xor_si256(v[d], v[a]), rotate_16);
+
+    v[c] = _mm256_add_epi32(v[c], v[d]);
+    v[b] = rotate_left::<12, 20>(_mm256_xor_si256(v[b], v[c]));
+
+    v[a] = _mm256_add_epi32(v[a], v[b]);
+    v[d] = _mm256_shuffle_epi8(_mm256_xor_si256(v[d], v[a]), rotate_8);
+
+    v[c] = _mm256_add_epi32(v[c], v[d]);
+    v[b] = rotate_left::<7, 25>(_mm256_xor_si256(v[b], v[c]));
+}
+
+/// Rotate every 32-bit lane left by `L` bits, where `R` is `32 - L`
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn rotate_left<const L: i32, const R: i32>(x: __m256i) -> __m256i {
+    _mm256_or_si256(_mm256_slli_epi32::<L>(x), _mm256_srli_epi32::<R>(x))
+}
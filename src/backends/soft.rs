@@ -0,0 +1,87 @@
+//! Portable 4-way ChaCha
+//!
+//! Same layout as the SIMD backends, with plain `[u32; 4]` arrays as lanes. The 4 blocks have no
+//! dependencies on each other, which keeps several instructions in flight on any CPU and leaves
+//! the compiler free to vectorise the lane loops where the target allows it. No unsafe code.
+
+use crate::wipe;
+
+/// Number of blocks generated per call
+pub(crate) const BLOCKS: usize = 4;
+
+type Lanes = [u32; BLOCKS];
+
+/// Generate 4 consecutive keystream blocks and advance the block counter in `state[12]` by 4
+pub(crate) fn keystream_blocks(state: &mut [u32; 16], double_rounds: usize, output: &mut [u8; 64 * BLOCKS]) {
+    let mut input = [[0u32; BLOCKS]; 16];
+    for (lanes, word) in input.iter_mut().zip(state.iter()) {
+        *lanes = [*word; BLOCKS];
+    }
+    for (block, counter) in input[12].iter_mut().enumerate() {
+        *counter = counter.wrapping_add(block as u32);
+    }
+
+    let mut v = input;
+    for _ in 0..double_rounds {
+        // column rounds
+        quarter_round(&mut v, 0, 4, 8, 12);
+        quarter_round(&mut v, 1, 5, 9, 13);
+        quarter_round(&mut v, 2, 6, 10, 14);
+        quarter_round(&mut v, 3, 7, 11, 15);
+
+        // diagonal rounds
+        quarter_round(&mut v, 0, 5, 10, 15);
+        quarter_round(&mut v, 1, 6, 11, 12);
+        quarter_round(&mut v, 2, 7, 8, 13);
+        quarter_round(&mut v, 3, 4, 9, 14);
+    }
+
+    for (block, out) in output.chunks_exact_mut(64).enumerate() {
+        for ((chunk, x), i) in out.chunks_exact_mut(4).zip(v.iter()).zip(input.iter()) {
+            chunk.copy_from_slice(&x[block].wrapping_add(i[block]).to_le_bytes());
+        }
+    }
+
+    state[12] = state[12].wrapping_add(BLOCKS as u32);
+
+    wipe(&mut input);
+    wipe(&mut v);
+}
+
+#[inline(always)]
+fn quarter_round(v: &mut [Lanes; 16], a: usize, b: usize, c: usize, d: usize) {
+    let (mut va, mut vb, mut vc, mut vd) = (v[a], v[b], v[c], v[d]);
+
+    va = add(va, vb);
+    vd = xor_rotate::<16>(vd, va);
+
+    vc = add(vc, vd);
+    vb = xor_rotate::<12>(vb, vc);
+
+    va = add(va, vb);
+    vd = xor_rotate::<8>(vd, va);
+
+    vc = add(vc, vd);
+    vb = xor_rotate::<7>(vb, vc);
+
+    (v[a], v[b], v[c], v[d]) = (va, vb, vc, vd);
+}
+
+#[inline(always)]
+fn add(x: Lanes, y: Lanes) -> Lanes {
+    let mut out = x;
+    for (o, y) in out.iter_mut().zip(y) {
+        *o = o.wrapping_add(y);
+    }
+    out
+}
+
+/// XOR the lanes of `x` and `y` and rotate every lane left by `N` bits
+#[inline(always)]
+fn xor_rotate<const N: u32>(x: Lanes, y: Lanes) -> Lanes {
+    let mut out = x;
+    for (o, y) in out.iter_mut().zip(y) {
+        *o = (*o ^ y).rotate_left(N);
+    }
+    out
+}
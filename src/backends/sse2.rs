@@ -0,0 +1,93 @@
+//! 4-way ChaCha with SSE2
+//!
+//! Each vector holds the same state word of 4 consecutive blocks, so the rounds are the scalar
+//! quarter rounds applied lane-wise. The blocks are transposed back to byte order on output.
+
+use crate::wipe;
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+/// Number of blocks generated per call
+pub(crate) const BLOCKS: usize = 4;
+
+/// Generate 4 consecutive keystream blocks and advance the block counter in `state[12]` by 4
+///
+/// # Safety
+/// The CPU must support SSE2.
+#[target_feature(enable = "sse2")]
+pub(crate) unsafe fn keystream_blocks(state: &mut [u32; 16], double_rounds: usize, output: &mut [u8; 64 * BLOCKS]) {
+    let mut input = [_mm_setzero_si128(); 16];
+    for (v, word) in input.iter_mut().zip(state.iter()) {
+        *v = _mm_set1_epi32(*word as i32);
+    }
+    input[12] = _mm_add_epi32(input[12], _mm_set_epi32(3, 2, 1, 0));
+
+    let mut v = input;
+    for _ in 0..double_rounds {
+        // column rounds
+        quarter_round(&mut v, 0, 4, 8, 12);
+        quarter_round(&mut v, 1, 5, 9, 13);
+        quarter_round(&mut v, 2, 6, 10, 14);
+        quarter_round(&mut v, 3, 7, 11, 15);
+
+        // diagonal rounds
+        quarter_round(&mut v, 0, 5, 10, 15);
+        quarter_round(&mut v, 1, 6, 11, 12);
+        quarter_round(&mut v, 2, 7, 8, 13);
+        quarter_round(&mut v, 3, 4, 9, 14);
+    }
+
+    for (x, i) in v.iter_mut().zip(input.iter()) {
+        *x = _mm_add_epi32(*x, *i);
+    }
+
+    // Transpose each group of 4 words so that every vector holds 16 bytes of a single block
+    let out = output.as_mut_ptr();
+    for group in 0..4 {
+        let [a, b, c, d] = [v[4 * group], v[4 * group + 1], v[4 * group + 2], v[4 * group + 3]];
+        let ab_lo = _mm_unpacklo_epi32(a, b);
+        let cd_lo = _mm_unpacklo_epi32(c, d);
+        let ab_hi = _mm_unpackhi_epi32(a, b);
+        let cd_hi = _mm_unpackhi_epi32(c, d);
+
+        let rows = [
+            _mm_unpacklo_epi64(ab_lo, cd_lo),
+            _mm_unpackhi_epi64(ab_lo, cd_lo),
+            _mm_unpacklo_epi64(ab_hi, cd_hi),
+            _mm_unpackhi_epi64(ab_hi, cd_hi),
+        ];
+        for (block, row) in rows.iter().enumerate() {
+            _mm_storeu_si128(out.add(64 * block + 16 * group) as *mut __m128i, *row);
+        }
+    }
+
+    state[12] = state[12].wrapping_add(BLOCKS as u32);
+
+    wipe(&mut input);
+    wipe(&mut v);
+}
+
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn quarter_round(v: &mut [__m128i; 16], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = _mm_add_epi32(v[a], v[b]);
+    v[d] = rotate_left::<16, 16>(_mm_xor_si128(v[d], v[a]));
+
+    v[c] = _mm_add_epi32(v[c], v[d]);
+    v[b] = rotate_left::<12, 20>(_mm_xor_si128(v[b], v[c]));
+
+    v[a] = _mm_add_epi32(v[a], v[b]);
+    v[d] = rotate_left::<8, 24>(_mm_xor_si128(v[d], v[a]));
+
+    v[c] = _mm_add_epi32(v[c], v[d]);
+    v[b] = rotate_left::<7, 25>(_mm_xor_si128(v[b], v[c]));
+}
+
+/// Rotate every 32-bit lane left by `L` bits, where `R` is `32 - L`
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn rotate_left<const L: i32, const R: i32>(x: __m128i) -> __m128i {
+    _mm_or_si128(_mm_slli_epi32::<L>(x), _mm_srli_epi32::<R>(x))
+}
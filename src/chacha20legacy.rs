@@ -0,0 +1,203 @@
+//! # ChaCha20 with the original 64-bit nonce
+//!
+//! Bernstein's original ChaCha20 layout uses a 64-bit block counter in `state[12..14]` and a
+//! 64-bit nonce in `state[14..16]`, instead of the 32-bit counter and 96-bit nonce of RFC 8439.
+//! A single stream can therefore be far longer than 256 GiB. Use this variant to interoperate
+//! with systems that still use the original layout.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::chacha20::ChaCha20;
+//! use chacha20imp::chacha20legacy::ChaCha20Legacy;
+//!
+//! let key = [0u8; 32];
+//! let nonce = [0u8; 8];
+//! let mut cipher = ChaCha20Legacy::new(&key, &nonce);
+//! let mut buf = [0u8; 64];
+//! cipher.apply_keystream(&mut buf);
+//! println!("Keystream: {:?}", buf);
+//! ```
+
+use crate::chacha20::{ChaCha20, ChaCha20Impl, Error, CONSTANTS};
+use crate::wipe;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+#[derive(Clone)]
+pub struct ChaCha20Legacy {
+    state: [u32; 16],
+    keystream: [u8; 64],
+    offset: usize,
+}
+
+impl ChaCha20Legacy {
+    /// Create a new instance from a fixed-size key and nonce, which cannot fail
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key
+    /// - `nonce` - A 64-bit nonce
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20legacy::ChaCha20Legacy;
+    /// let chacha = ChaCha20Legacy::from_key_nonce(&[0u8; 32], &[0u8; 8]);
+    /// ```
+    pub fn from_key_nonce(key: &[u8; 32], nonce: &[u8; 8]) -> Self {
+        let mut state = [0u32; 16];
+        state[0..4].copy_from_slice(&CONSTANTS);
+
+        for (word, chunk) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for (word, chunk) in state[14..16].iter_mut().zip(nonce.chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        let chacha = ChaCha20Legacy {
+            state,
+            keystream: [0u8; 64],
+            offset: 64,
+        };
+
+        wipe(&mut state);
+        chacha
+    }
+
+    fn counter(&self) -> u64 {
+        (self.state[13] as u64) << 32 | self.state[12] as u64
+    }
+
+    fn set_counter(&mut self, counter: u64) {
+        self.state[12] = counter as u32;
+        self.state[13] = (counter >> 32) as u32;
+    }
+
+    fn next_block(&mut self) {
+        ChaCha20Impl::chacha20_keystream_block(&self.state, &mut self.keystream);
+        self.set_counter(self.counter().wrapping_add(1));
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for ChaCha20Legacy {
+    /// Wipe the key, nonce, counter and buffered keystream
+    fn zeroize(&mut self) {
+        self.state.zeroize();
+        self.keystream.zeroize();
+        self.offset.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ChaCha20Legacy {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for ChaCha20Legacy {}
+
+impl ChaCha20 for ChaCha20Legacy {
+    const NONCE_SIZE: usize = 8;
+
+    /// Create a new ChaCha20Legacy instance
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key as a slice of 32 u8 values
+    /// - `nonce` - A 64-bit nonce as a slice of 8 u8 values
+    ///
+    /// ## Returns
+    /// A new ChaCha20Legacy instance
+    ///
+    /// ## Panics
+    /// If the key or nonce has the wrong length
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20::ChaCha20;
+    /// use chacha20imp::chacha20legacy::ChaCha20Legacy;
+    /// let chacha = ChaCha20Legacy::new(&[0u8; 32], &[0u8; 8]);
+    /// ```
+    fn new(key: &[u8], nonce: &[u8]) -> Self {
+        match (key.try_into(), nonce.try_into()) {
+            (Ok(key), Ok(nonce)) => Self::from_key_nonce(key, nonce),
+            (Err(_), _) => panic!("Key must be 256 bits (32 u8 values)"),
+            (_, Err(_)) => panic!("Nonce must be 64 bits (8 u8 values)"),
+        }
+    }
+
+    /// Apply the keystream to the input
+    ///
+    /// ## Arguments
+    /// - `input` - A mutable slice of u8 values to apply the keystream to
+    fn apply_keystream(&mut self, input: &mut [u8]) {
+        for byte in input.iter_mut() {
+            if self.offset == 64 {
+                self.next_block();
+                self.offset = 0;
+            }
+            *byte ^= self.keystream[self.offset];
+            self.offset += 1;
+        }
+    }
+
+    /// Seek to a specific block in the keystream
+    ///
+    /// ## Arguments
+    /// a `pos` u32 value to seek to. Use [`ChaCha20::seek_pos`] to reach blocks past `u32::MAX`
+    fn seek(&mut self, pos: u32) {
+        self.set_counter(pos as u64);
+        self.offset = 64;
+    }
+
+    /// Seek to a byte position in the keystream
+    ///
+    /// Every u64 byte position fits in the 64-bit block counter, so this never fails.
+    ///
+    /// ## Arguments
+    /// - `pos` - A u64 byte offset from the start of the keystream
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20::ChaCha20;
+    /// use chacha20imp::chacha20legacy::ChaCha20Legacy;
+    /// let mut chacha = ChaCha20Legacy::new(&[0u8; 32], &[0u8; 8]);
+    /// chacha.seek_pos(1 << 40).unwrap();
+    /// assert_eq!(chacha.current_pos(), 1 << 40);
+    /// ```
+    fn seek_pos(&mut self, pos: u64) -> Result<(), Error> {
+        self.set_counter(pos / 64);
+        self.offset = 64;
+
+        let offset = (pos % 64) as usize;
+        if offset != 0 {
+            self.next_block();
+            self.offset = offset;
+        }
+
+        Ok(())
+    }
+
+    /// Get the current byte position in the keystream
+    fn current_pos(&self) -> u64 {
+        if self.offset == 64 {
+            self.counter().wrapping_mul(64)
+        } else {
+            self.counter().wrapping_sub(1).wrapping_mul(64) + self.offset as u64
+        }
+    }
+
+    /// Get the number of keystream bytes left before the block counter wraps around
+    ///
+    /// The 64-bit counter covers 2^70 bytes, so the result saturates at `u64::MAX`.
+    fn remaining_keystream(&self) -> u64 {
+        let pos = if self.offset == 64 {
+            self.counter() as u128 * 64
+        } else {
+            self.counter().wrapping_sub(1) as u128 * 64 + self.offset as u128
+        };
+
+        ((1u128 << 70) - pos).min(u64::MAX as u128) as u64
+    }
+}
@@ -0,0 +1,347 @@
+//! # ChaCha20-Poly1305 AEAD
+//!
+//! Authenticated encryption with associated data as specified in RFC 8439. The Poly1305 one-time
+//! key is taken from block 0 of the ChaCha20 keystream and the payload is encrypted starting at
+//! block counter 1, which limits a message to [`MAX_MESSAGE_SIZE`] bytes before the counter would
+//! wrap around to the block holding the Poly1305 key.
+//!
+//! [`XChaCha20Poly1305`] is the extended-nonce variant with 192-bit nonces. It is compatible with
+//! libsodium's `crypto_aead_xchacha20poly1305_ietf`.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::chacha20poly1305::ChaCha20Poly1305;
+//!
+//! let aead = ChaCha20Poly1305::new(&[0u8; 32]);
+//! let nonce = [0u8; 12];
+//! let mut buffer = *b"Hello, ChaCha20!";
+//! let tag = aead.encrypt_in_place_detached(&nonce, b"header", &mut buffer);
+//! aead.decrypt_in_place_detached(&nonce, b"header", &mut buffer, &tag).unwrap();
+//! assert_eq!(&buffer, b"Hello, ChaCha20!");
+//! ```
+//!
+//! With the `alloc` feature, `ChaCha20Poly1305::encrypt` and `ChaCha20Poly1305::decrypt`
+//! return the ciphertext with the tag appended as a `Vec<u8>`.
+
+use crate::chacha20::{ChaCha20, ChaCha20Impl};
+use crate::poly1305::{poly1305_key_gen, Poly1305};
+use crate::wipe;
+use crate::xchacha20::hchacha20;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Size in bytes of the authentication tag
+pub const TAG_SIZE: usize = 16;
+
+/// Largest message in bytes, the 2^32 - 1 keystream blocks after block 0
+pub const MAX_MESSAGE_SIZE: u64 = u32::MAX as u64 * 64;
+
+/// Errors returned by the ChaCha20-Poly1305 AEAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The ciphertext, associated data or tag failed authentication
+    InvalidTag,
+    /// The message is longer than [`MAX_MESSAGE_SIZE`]
+    MessageTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidTag => write!(f, "Authentication tag does not match"),
+            Error::MessageTooLong => write!(f, "Message is too long"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+pub struct ChaCha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl ChaCha20Poly1305 {
+    /// Create a new ChaCha20-Poly1305 instance
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20poly1305::ChaCha20Poly1305;
+    /// let aead = ChaCha20Poly1305::new(&[0u8; 32]);
+    /// ```
+    pub fn new(key: &[u8; 32]) -> Self {
+        ChaCha20Poly1305 { key: *key }
+    }
+
+    /// Encrypt a message and append the authentication tag
+    ///
+    /// ## Arguments
+    /// - `nonce` - A 96-bit nonce, which must never be reused with the same key
+    /// - `aad` - Associated data that is authenticated but not encrypted
+    /// - `plaintext` - The message to encrypt
+    ///
+    /// ## Returns
+    /// The ciphertext followed by the 16-byte tag
+    ///
+    /// ## Panics
+    /// If the plaintext is longer than [`MAX_MESSAGE_SIZE`]
+    #[cfg(feature = "alloc")]
+    pub fn encrypt(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(plaintext.len() + TAG_SIZE);
+        buffer.extend_from_slice(plaintext);
+        let tag = self.encrypt_in_place_detached(nonce, aad, &mut buffer);
+        buffer.extend_from_slice(&tag);
+        buffer
+    }
+
+    /// Verify and decrypt a message produced by [`ChaCha20Poly1305::encrypt`]
+    ///
+    /// ## Arguments
+    /// - `nonce` - The 96-bit nonce used for encryption
+    /// - `aad` - The associated data used for encryption
+    /// - `ciphertext` - The ciphertext followed by the 16-byte tag
+    ///
+    /// ## Returns
+    /// The plaintext, `Error::InvalidTag` if authentication fails, or `Error::MessageTooLong` if
+    /// the ciphertext is longer than [`MAX_MESSAGE_SIZE`]
+    #[cfg(feature = "alloc")]
+    pub fn decrypt(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        if ciphertext.len() < TAG_SIZE {
+            return Err(Error::InvalidTag);
+        }
+
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+        let mut tag_bytes = [0u8; TAG_SIZE];
+        tag_bytes.copy_from_slice(tag);
+
+        let mut buffer = ciphertext.to_vec();
+        self.decrypt_in_place_detached(nonce, aad, &mut buffer, &tag_bytes)?;
+        Ok(buffer)
+    }
+
+    /// Encrypt a buffer in place and return the tag separately
+    ///
+    /// ## Arguments
+    /// - `nonce` - A 96-bit nonce, which must never be reused with the same key
+    /// - `aad` - Associated data that is authenticated but not encrypted
+    /// - `buffer` - The plaintext, replaced by the ciphertext
+    ///
+    /// ## Returns
+    /// The 16-byte authentication tag
+    ///
+    /// ## Panics
+    /// If the buffer is longer than [`MAX_MESSAGE_SIZE`], before any of it is modified
+    pub fn encrypt_in_place_detached(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut [u8]) -> [u8; TAG_SIZE] {
+        assert!(check_len(buffer).is_ok(), "message is longer than MAX_MESSAGE_SIZE");
+
+        let (mut cipher, mut mac_key) = self.init(nonce);
+        cipher.apply_keystream(buffer);
+        let tag = authenticate(&mac_key, aad, buffer).finalize();
+
+        wipe(&mut mac_key);
+
+        tag
+    }
+
+    /// Verify a detached tag and decrypt a buffer in place
+    ///
+    /// The buffer is left untouched when authentication fails.
+    ///
+    /// ## Arguments
+    /// - `nonce` - The 96-bit nonce used for encryption
+    /// - `aad` - The associated data used for encryption
+    /// - `buffer` - The ciphertext, replaced by the plaintext
+    /// - `tag` - The 16-byte authentication tag
+    ///
+    /// ## Returns
+    /// `Error::InvalidTag` if authentication fails, or `Error::MessageTooLong` if the buffer is
+    /// longer than [`MAX_MESSAGE_SIZE`]
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; TAG_SIZE],
+    ) -> Result<(), Error> {
+        check_len(buffer)?;
+
+        let (mut cipher, mut mac_key) = self.init(nonce);
+        let verified = authenticate(&mac_key, aad, buffer).verify(tag);
+
+        wipe(&mut mac_key);
+
+        verified.map_err(|_| Error::InvalidTag)?;
+
+        cipher.apply_keystream(buffer);
+        Ok(())
+    }
+
+    /// Derive the Poly1305 key from block 0 and leave the cipher at block 1
+    fn init(&self, nonce: &[u8; 12]) -> (ChaCha20Impl, [u8; 32]) {
+        let mac_key = poly1305_key_gen(&self.key, nonce);
+
+        let mut cipher = ChaCha20Impl::from_key_nonce(&self.key, nonce);
+        cipher.seek(1);
+
+        (cipher, mac_key)
+    }
+}
+
+pub struct XChaCha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl XChaCha20Poly1305 {
+    /// Create a new XChaCha20-Poly1305 instance
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20poly1305::XChaCha20Poly1305;
+    /// let aead = XChaCha20Poly1305::new(&[0u8; 32]);
+    /// ```
+    pub fn new(key: &[u8; 32]) -> Self {
+        XChaCha20Poly1305 { key: *key }
+    }
+
+    /// Encrypt a message and append the authentication tag
+    ///
+    /// ## Arguments
+    /// - `nonce` - A 192-bit nonce, which may be generated at random
+    /// - `aad` - Associated data that is authenticated but not encrypted
+    /// - `plaintext` - The message to encrypt
+    ///
+    /// ## Returns
+    /// The ciphertext followed by the 16-byte tag
+    ///
+    /// ## Panics
+    /// If the plaintext is longer than [`MAX_MESSAGE_SIZE`]
+    #[cfg(feature = "alloc")]
+    pub fn encrypt(&self, nonce: &[u8; 24], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let (aead, nonce) = self.derive(nonce);
+        aead.encrypt(&nonce, aad, plaintext)
+    }
+
+    /// Verify and decrypt a message produced by [`XChaCha20Poly1305::encrypt`]
+    ///
+    /// ## Arguments
+    /// - `nonce` - The 192-bit nonce used for encryption
+    /// - `aad` - The associated data used for encryption
+    /// - `ciphertext` - The ciphertext followed by the 16-byte tag
+    ///
+    /// ## Returns
+    /// The plaintext, `Error::InvalidTag` if authentication fails, or `Error::MessageTooLong` if
+    /// the ciphertext is longer than [`MAX_MESSAGE_SIZE`]
+    #[cfg(feature = "alloc")]
+    pub fn decrypt(&self, nonce: &[u8; 24], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let (aead, nonce) = self.derive(nonce);
+        aead.decrypt(&nonce, aad, ciphertext)
+    }
+
+    /// Encrypt a buffer in place and return the tag separately
+    ///
+    /// ## Arguments
+    /// - `nonce` - A 192-bit nonce, which may be generated at random
+    /// - `aad` - Associated data that is authenticated but not encrypted
+    /// - `buffer` - The plaintext, replaced by the ciphertext
+    ///
+    /// ## Returns
+    /// The 16-byte authentication tag
+    ///
+    /// ## Panics
+    /// If the buffer is longer than [`MAX_MESSAGE_SIZE`], before any of it is modified
+    pub fn encrypt_in_place_detached(&self, nonce: &[u8; 24], aad: &[u8], buffer: &mut [u8]) -> [u8; TAG_SIZE] {
+        let (aead, nonce) = self.derive(nonce);
+        aead.encrypt_in_place_detached(&nonce, aad, buffer)
+    }
+
+    /// Verify a detached tag and decrypt a buffer in place
+    ///
+    /// The buffer is left untouched when authentication fails.
+    ///
+    /// ## Arguments
+    /// - `nonce` - The 192-bit nonce used for encryption
+    /// - `aad` - The associated data used for encryption
+    /// - `buffer` - The ciphertext, replaced by the plaintext
+    /// - `tag` - The 16-byte authentication tag
+    ///
+    /// ## Returns
+    /// `Error::InvalidTag` if authentication fails, or `Error::MessageTooLong` if the buffer is
+    /// longer than [`MAX_MESSAGE_SIZE`]
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &[u8; 24],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8; TAG_SIZE],
+    ) -> Result<(), Error> {
+        let (aead, nonce) = self.derive(nonce);
+        aead.decrypt_in_place_detached(&nonce, aad, buffer, tag)
+    }
+
+    /// Derive the HChaCha20 subkey and the 96-bit nonce of the inner ChaCha20-Poly1305
+    fn derive(&self, nonce: &[u8; 24]) -> (ChaCha20Poly1305, [u8; 12]) {
+        let mut input = [0u8; 16];
+        input.copy_from_slice(&nonce[..16]);
+
+        let mut inner_nonce = [0u8; 12];
+        inner_nonce[4..].copy_from_slice(&nonce[16..]);
+
+        let mut subkey = hchacha20(&self.key, &input);
+        let aead = ChaCha20Poly1305::new(&subkey);
+
+        wipe(&mut subkey);
+        (aead, inner_nonce)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ChaCha20Poly1305 {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for ChaCha20Poly1305 {}
+
+#[cfg(feature = "zeroize")]
+impl Drop for XChaCha20Poly1305 {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for XChaCha20Poly1305 {}
+
+/// Refuse messages that would run the block counter past 2^32 - 1 and wrap it around to block 0,
+/// whose keystream is the Poly1305 key
+fn check_len(buffer: &[u8]) -> Result<(), Error> {
+    if buffer.len() as u64 > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLong);
+    }
+    Ok(())
+}
+
+/// Feed `aad || pad16 || ciphertext || pad16 || len(aad) || len(ciphertext)` to Poly1305
+fn authenticate(mac_key: &[u8; 32], aad: &[u8], ciphertext: &[u8]) -> Poly1305 {
+    let mut mac = Poly1305::new(mac_key);
+    mac.update_padded(aad);
+    mac.update_padded(ciphertext);
+
+    let mut lengths = [0u8; 16];
+    lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    mac.update(&lengths);
+
+    mac
+}
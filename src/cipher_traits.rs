@@ -0,0 +1,161 @@
+//! # RustCrypto `cipher` traits
+//!
+//! [`KeyIvInit`], [`StreamCipher`] and [`StreamCipherSeek`] implementations for the stream
+//! ciphers, so they can be used by crates that are generic over those traits. Available with the
+//! `cipher` feature, which also re-exports the `cipher` crate.
+//!
+//! As required by `StreamCipher`, `try_apply_keystream` fails without touching the buffer when
+//! the input would run past the end of the block counter, instead of wrapping around.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::chacha20::ChaCha20Impl;
+//! use chacha20imp::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+//!
+//! let mut cipher = ChaCha20Impl::new(&[0u8; 32].into(), &[0u8; 12].into());
+//! let mut buf = [0u8; 100];
+//! cipher.apply_keystream(&mut buf);
+//! assert_eq!(cipher.current_pos::<u64>(), 100);
+//! ```
+
+use crate::chacha20::{ChaCha20, ChaChaImpl};
+use crate::chacha20legacy::ChaCha20Legacy;
+use crate::wipe;
+use crate::xchacha20::XChaCha20;
+use cipher::consts::{U12, U24, U32, U8};
+use cipher::inout::InOutBuf;
+use cipher::{
+    Iv, IvSizeUser, Key, KeyIvInit, KeySizeUser, OverflowError, SeekNum, StreamCipher, StreamCipherError,
+    StreamCipherSeek,
+};
+
+/// Apply the keystream, or fail without touching the buffer if it would run past the end
+fn try_apply<C: ChaCha20>(cipher: &mut C, buf: InOutBuf<'_, '_, u8>) -> Result<(), StreamCipherError> {
+    if buf.len() as u64 > cipher.remaining_keystream() {
+        return Err(StreamCipherError);
+    }
+
+    // Generate the keystream in chunks large enough for the multi-block backends
+    let mut keystream = [0u8; 512];
+    let mut buf = buf;
+    while !buf.is_empty() {
+        let len = buf.len().min(keystream.len());
+        let (mut chunk, rest) = buf.split_at(len);
+
+        keystream[..len].fill(0);
+        cipher.apply_keystream(&mut keystream[..len]);
+        chunk.xor_in2out(&keystream[..len]);
+        buf = rest;
+    }
+
+    wipe(&mut keystream);
+
+    Ok(())
+}
+
+fn try_current_pos<C: ChaCha20, T: SeekNum>(cipher: &C) -> Result<T, OverflowError> {
+    let pos = cipher.current_pos();
+    // `SeekNum` expects the number of the next block to generate when a block is partially used
+    T::from_block_byte(pos.div_ceil(64), (pos % 64) as u8, 64)
+}
+
+fn try_seek<C: ChaCha20, T: SeekNum>(cipher: &mut C, pos: T) -> Result<(), StreamCipherError> {
+    let (block, byte): (u64, u8) = pos.into_block_byte(64)?;
+    // Negative positions come back with an out-of-range byte offset
+    if byte >= 64 {
+        return Err(StreamCipherError);
+    }
+
+    let pos = block.checked_mul(64).ok_or(StreamCipherError)? + byte as u64;
+    cipher.seek_pos(pos).map_err(|_| StreamCipherError)
+}
+
+impl<const ROUNDS: usize> KeySizeUser for ChaChaImpl<ROUNDS> {
+    type KeySize = U32;
+}
+
+impl<const ROUNDS: usize> IvSizeUser for ChaChaImpl<ROUNDS> {
+    type IvSize = U12;
+}
+
+impl<const ROUNDS: usize> KeyIvInit for ChaChaImpl<ROUNDS> {
+    fn new(key: &Key<Self>, iv: &Iv<Self>) -> Self {
+        Self::from_key_nonce(&(*key).into(), &(*iv).into())
+    }
+}
+
+impl<const ROUNDS: usize> StreamCipher for ChaChaImpl<ROUNDS> {
+    fn try_apply_keystream_inout(&mut self, buf: InOutBuf<'_, '_, u8>) -> Result<(), StreamCipherError> {
+        try_apply(self, buf)
+    }
+}
+
+impl<const ROUNDS: usize> StreamCipherSeek for ChaChaImpl<ROUNDS> {
+    fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+        try_current_pos(self)
+    }
+
+    fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+        try_seek(self, pos)
+    }
+}
+
+impl KeySizeUser for XChaCha20 {
+    type KeySize = U32;
+}
+
+impl IvSizeUser for XChaCha20 {
+    type IvSize = U24;
+}
+
+impl KeyIvInit for XChaCha20 {
+    fn new(key: &Key<Self>, iv: &Iv<Self>) -> Self {
+        Self::from_key_nonce(&(*key).into(), &(*iv).into())
+    }
+}
+
+impl StreamCipher for XChaCha20 {
+    fn try_apply_keystream_inout(&mut self, buf: InOutBuf<'_, '_, u8>) -> Result<(), StreamCipherError> {
+        try_apply(self, buf)
+    }
+}
+
+impl StreamCipherSeek for XChaCha20 {
+    fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+        try_current_pos(self)
+    }
+
+    fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+        try_seek(self, pos)
+    }
+}
+
+impl KeySizeUser for ChaCha20Legacy {
+    type KeySize = U32;
+}
+
+impl IvSizeUser for ChaCha20Legacy {
+    type IvSize = U8;
+}
+
+impl KeyIvInit for ChaCha20Legacy {
+    fn new(key: &Key<Self>, iv: &Iv<Self>) -> Self {
+        Self::from_key_nonce(&(*key).into(), &(*iv).into())
+    }
+}
+
+impl StreamCipher for ChaCha20Legacy {
+    fn try_apply_keystream_inout(&mut self, buf: InOutBuf<'_, '_, u8>) -> Result<(), StreamCipherError> {
+        try_apply(self, buf)
+    }
+}
+
+impl StreamCipherSeek for ChaCha20Legacy {
+    fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+        try_current_pos(self)
+    }
+
+    fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+        try_seek(self, pos)
+    }
+}
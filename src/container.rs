@@ -0,0 +1,611 @@
+//! # Encrypted container format
+//!
+//! A self-describing file format around the [`crate::stream`] AEAD. A container is a header
+//! followed by the STREAM segments:
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  magic "C20AEAD\0"
+//!      8     1  format version, currently 1
+//!      9     1  algorithm: 1 = ChaCha20-Poly1305, 2 = XChaCha20-Poly1305
+//!     10     1  KDF identifier, 0 when the key is used directly
+//!     11     1  length of the KDF parameter block, 0 without a KDF
+//!     12     4  plaintext bytes per segment, little-endian
+//!     16    24  salt
+//!     40  7/19  nonce prefix of the segments, 7 bytes for ChaCha20-Poly1305, 19 for XChaCha20
+//!      .     .  KDF parameter block
+//!      .    32  key check value
+//!      .    16  header tag
+//! ```
+//!
+//! The key never encrypts data directly. The first 96 bytes of XChaCha20 keystream under the key,
+//! with the salt as nonce, give a per-file segment key, a one-time Poly1305 key and the key check
+//! value. A wrong key is therefore reported as [`Error::WrongKey`] before anything is decrypted,
+//! and the header tag, a Poly1305 MAC of every header byte before it, rejects a modified header
+//! with [`Error::InvalidHeaderTag`].
+//!
+//! [`Header::parse`] validates the structure without a key and never panics, whatever the input.
+//! It only accepts the canonical encoding, so a parsed header re-encodes to the same bytes.
+//!
+//! ## Example
+//! ```rust
+//! # #[cfg(feature = "std")]
+//! # {
+//! use chacha20imp::container::{Algorithm, ContainerReader, ContainerWriter, Header};
+//! use std::io::{Read, Write};
+//!
+//! let key = [0x42u8; 32];
+//! // Use random bytes from a CSPRNG for the salt and the nonce
+//! let header = Header::new(Algorithm::XChaCha20Poly1305, [1u8; 24], [2u8; 19]);
+//!
+//! let mut writer = ContainerWriter::new(Vec::new(), &key, &header).unwrap();
+//! writer.write_all(b"Hello, world!").unwrap();
+//! let container = writer.finish().unwrap();
+//!
+//! let mut reader = ContainerReader::new(&container[..], &key).unwrap();
+//! let mut plaintext = String::new();
+//! reader.read_to_string(&mut plaintext).unwrap();
+//! assert_eq!(plaintext, "Hello, world!");
+//! # }
+//! ```
+
+use crate::chacha20::ChaCha20;
+use crate::poly1305::Poly1305;
+use crate::stream::{DEFAULT_SEGMENT_SIZE, NONCE_PREFIX_SIZE, X_NONCE_PREFIX_SIZE};
+use crate::wipe;
+use crate::xchacha20::XChaCha20;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Magic bytes at the start of every container
+pub const MAGIC: [u8; 8] = *b"C20AEAD\0";
+
+/// Format version written by this crate
+pub const VERSION: u8 = 1;
+
+/// Size in bytes of the salt
+pub const SALT_SIZE: usize = 24;
+
+/// Size in bytes of the key check value
+pub const KEY_CHECK_SIZE: usize = 32;
+
+/// Size in bytes of the header tag
+pub const HEADER_TAG_SIZE: usize = 16;
+
+/// Largest accepted segment size, which bounds the memory needed to read a container
+pub const MAX_SEGMENT_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Largest accepted KDF parameter block
+pub const MAX_KDF_PARAMS_SIZE: usize = 128;
+
+/// Size of the fields before the salt, which determine the length of the rest of the header
+const FIXED_SIZE: usize = 16;
+
+/// Largest possible header
+pub const MAX_HEADER_SIZE: usize =
+    FIXED_SIZE + SALT_SIZE + X_NONCE_PREFIX_SIZE + MAX_KDF_PARAMS_SIZE + KEY_CHECK_SIZE + HEADER_TAG_SIZE;
+
+/// Errors returned when reading or writing a container header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The data does not start with the container magic bytes
+    InvalidMagic,
+    /// The container was written with a format version this crate does not know
+    UnsupportedVersion,
+    /// The algorithm identifier is not a known algorithm
+    UnknownAlgorithm,
+    /// The segment size is 0 or larger than [`MAX_SEGMENT_SIZE`]
+    InvalidSegmentSize,
+    /// The KDF parameter block is too long, or present without a KDF
+    InvalidKdfParams,
+    /// The data ends before the end of the header
+    Truncated,
+    /// The key check value does not match the key
+    WrongKey,
+    /// The header was modified
+    InvalidHeaderTag,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidMagic => write!(f, "Not an encrypted container"),
+            Error::UnsupportedVersion => write!(f, "Unsupported container version"),
+            Error::UnknownAlgorithm => write!(f, "Unknown container algorithm"),
+            Error::InvalidSegmentSize => write!(f, "Invalid container segment size"),
+            Error::InvalidKdfParams => write!(f, "Invalid container KDF parameters"),
+            Error::Truncated => write!(f, "Container header is truncated"),
+            Error::WrongKey => write!(f, "Wrong key for this container"),
+            Error::InvalidHeaderTag => write!(f, "Container header authentication failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// AEAD used for the segments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// ChaCha20-Poly1305 with a 7-byte nonce prefix
+    ChaCha20Poly1305,
+    /// XChaCha20-Poly1305 with a 19-byte nonce prefix
+    XChaCha20Poly1305,
+}
+
+impl Algorithm {
+    fn id(self) -> u8 {
+        match self {
+            Algorithm::ChaCha20Poly1305 => 1,
+            Algorithm::XChaCha20Poly1305 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, Error> {
+        match id {
+            1 => Ok(Algorithm::ChaCha20Poly1305),
+            2 => Ok(Algorithm::XChaCha20Poly1305),
+            _ => Err(Error::UnknownAlgorithm),
+        }
+    }
+
+    /// Size in bytes of the nonce prefix stored in the header
+    pub fn nonce_prefix_size(self) -> usize {
+        match self {
+            Algorithm::ChaCha20Poly1305 => NONCE_PREFIX_SIZE,
+            Algorithm::XChaCha20Poly1305 => X_NONCE_PREFIX_SIZE,
+        }
+    }
+}
+
+/// Parameters of the key derivation function that turned a password into the key
+///
+/// The container stores them as an opaque block next to the KDF identifier; interpreting them is
+/// up to the KDF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    id: u8,
+    params: Vec<u8>,
+}
+
+impl KdfParams {
+    /// Create a KDF parameter block
+    ///
+    /// ## Returns
+    /// `Error::InvalidKdfParams` if `id` is 0 or `params` is longer than [`MAX_KDF_PARAMS_SIZE`]
+    pub fn new(id: u8, params: &[u8]) -> Result<Self, Error> {
+        if id == 0 || params.len() > MAX_KDF_PARAMS_SIZE {
+            return Err(Error::InvalidKdfParams);
+        }
+
+        Ok(KdfParams {
+            id,
+            params: params.to_vec(),
+        })
+    }
+
+    /// Get the KDF identifier
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Get the encoded parameters
+    pub fn params(&self) -> &[u8] {
+        &self.params
+    }
+}
+
+/// Keys derived from the container key and the salt
+struct FileKeys {
+    segment_key: [u8; 32],
+    mac_key: [u8; 32],
+    key_check: [u8; KEY_CHECK_SIZE],
+}
+
+impl FileKeys {
+    fn derive(key: &[u8; 32], salt: &[u8; SALT_SIZE]) -> Self {
+        let mut keystream = [0u8; 96];
+        XChaCha20::from_key_nonce(key, salt).apply_keystream(&mut keystream);
+
+        let mut keys = FileKeys {
+            segment_key: [0u8; 32],
+            mac_key: [0u8; 32],
+            key_check: [0u8; KEY_CHECK_SIZE],
+        };
+        keys.segment_key.copy_from_slice(&keystream[..32]);
+        keys.mac_key.copy_from_slice(&keystream[32..64]);
+        keys.key_check.copy_from_slice(&keystream[64..]);
+
+        wipe(&mut keystream);
+
+        keys
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for FileKeys {
+    fn drop(&mut self) {
+        self.segment_key.zeroize();
+        self.mac_key.zeroize();
+    }
+}
+
+/// Container header
+///
+/// Created with [`Header::new`] for writing or [`Header::parse`] for reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    algorithm: Algorithm,
+    segment_size: u32,
+    salt: [u8; SALT_SIZE],
+    nonce: [u8; X_NONCE_PREFIX_SIZE],
+    kdf: Option<KdfParams>,
+    /// Key check value and tag of a parsed header, zero for a new one
+    key_check: [u8; KEY_CHECK_SIZE],
+    tag: [u8; HEADER_TAG_SIZE],
+}
+
+impl Header {
+    /// Create a header for a new container with [`DEFAULT_SEGMENT_SIZE`] segments
+    ///
+    /// ## Arguments
+    /// - `algorithm` - The AEAD for the segments
+    /// - `salt` - Random bytes, unique per container
+    /// - `nonce` - Random bytes for the nonce prefix; only the first 7 are used with
+    ///   ChaCha20-Poly1305
+    pub fn new(algorithm: Algorithm, salt: [u8; SALT_SIZE], nonce: [u8; X_NONCE_PREFIX_SIZE]) -> Self {
+        let mut nonce = nonce;
+        nonce[algorithm.nonce_prefix_size()..].fill(0);
+
+        Header {
+            algorithm,
+            segment_size: DEFAULT_SEGMENT_SIZE as u32,
+            salt,
+            nonce,
+            kdf: None,
+            key_check: [0u8; KEY_CHECK_SIZE],
+            tag: [0u8; HEADER_TAG_SIZE],
+        }
+    }
+
+    /// Set the amount of plaintext per segment
+    ///
+    /// ## Panics
+    /// If `segment_size` is 0 or larger than [`MAX_SEGMENT_SIZE`]
+    pub fn with_segment_size(mut self, segment_size: u32) -> Self {
+        assert!(
+            segment_size > 0 && segment_size <= MAX_SEGMENT_SIZE,
+            "Segment size must be between 1 and {MAX_SEGMENT_SIZE}"
+        );
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Record the parameters of the KDF the key was derived with
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Self {
+        self.kdf = Some(kdf);
+        self
+    }
+
+    /// Get the segment algorithm
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Get the amount of plaintext per segment
+    pub fn segment_size(&self) -> u32 {
+        self.segment_size
+    }
+
+    /// Get the salt
+    pub fn salt(&self) -> &[u8; SALT_SIZE] {
+        &self.salt
+    }
+
+    /// Get the nonce prefix of the segments
+    pub fn nonce_prefix(&self) -> &[u8] {
+        &self.nonce[..self.algorithm.nonce_prefix_size()]
+    }
+
+    /// Get the KDF parameters, if the key was derived from a password
+    pub fn kdf(&self) -> Option<&KdfParams> {
+        self.kdf.as_ref()
+    }
+
+    /// Length of the encoded header in bytes
+    pub fn encoded_len(&self) -> usize {
+        FIXED_SIZE
+            + SALT_SIZE
+            + self.algorithm.nonce_prefix_size()
+            + self.kdf_params().len()
+            + KEY_CHECK_SIZE
+            + HEADER_TAG_SIZE
+    }
+
+    /// Encode the header, with the key check value and tag for `key`
+    pub fn encode(&self, key: &[u8; 32]) -> Vec<u8> {
+        self.encode_with(&FileKeys::derive(key, &self.salt))
+    }
+
+    /// Encode the header with the key check value and tag of already derived keys
+    fn encode_with(&self, keys: &FileKeys) -> Vec<u8> {
+        let mut bytes = self.encode_fields();
+        bytes.extend_from_slice(&keys.key_check);
+        let mut mac = Poly1305::new(&keys.mac_key);
+        mac.update(&bytes);
+        bytes.extend_from_slice(&mac.finalize());
+        bytes
+    }
+
+    /// Parse a header from the start of `bytes`, without checking the key
+    ///
+    /// Every field is validated, and only the canonical encoding is accepted. Never panics.
+    ///
+    /// ## Returns
+    /// The header and its length in bytes, after which the segments start
+    pub fn parse(bytes: &[u8]) -> Result<(Self, usize), Error> {
+        let len = Self::parse_len(bytes)?;
+        let bytes = bytes.get(..len).ok_or(Error::Truncated)?;
+
+        let algorithm = Algorithm::from_id(bytes[9])?;
+        let kdf_len = bytes[11] as usize;
+        let segment_size = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+
+        let mut rest = &bytes[FIXED_SIZE..];
+        let mut salt = [0u8; SALT_SIZE];
+        salt.copy_from_slice(take(&mut rest, SALT_SIZE));
+        let mut nonce = [0u8; X_NONCE_PREFIX_SIZE];
+        nonce[..algorithm.nonce_prefix_size()].copy_from_slice(take(&mut rest, algorithm.nonce_prefix_size()));
+        let kdf_params = take(&mut rest, kdf_len);
+        let mut key_check = [0u8; KEY_CHECK_SIZE];
+        key_check.copy_from_slice(take(&mut rest, KEY_CHECK_SIZE));
+        let mut tag = [0u8; HEADER_TAG_SIZE];
+        tag.copy_from_slice(take(&mut rest, HEADER_TAG_SIZE));
+
+        let kdf = match bytes[10] {
+            0 => None,
+            id => Some(KdfParams::new(id, kdf_params)?),
+        };
+
+        let header = Header {
+            algorithm,
+            segment_size,
+            salt,
+            nonce,
+            kdf,
+            key_check,
+            tag,
+        };
+        Ok((header, len))
+    }
+
+    /// Get the length of the header starting at `bytes` from its fixed fields
+    ///
+    /// Only needs the first 16 bytes, so a reader knows how much more to read.
+    ///
+    /// ## Returns
+    /// The header length, or an error if the fixed fields are invalid or incomplete
+    pub fn parse_len(bytes: &[u8]) -> Result<usize, Error> {
+        let magic_len = bytes.len().min(MAGIC.len());
+        if bytes[..magic_len] != MAGIC[..magic_len] {
+            return Err(Error::InvalidMagic);
+        }
+        if bytes.len() < FIXED_SIZE {
+            return Err(Error::Truncated);
+        }
+        if bytes[8] != VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let algorithm = Algorithm::from_id(bytes[9])?;
+        let kdf_len = bytes[11] as usize;
+        if kdf_len > MAX_KDF_PARAMS_SIZE || (bytes[10] == 0 && kdf_len != 0) {
+            return Err(Error::InvalidKdfParams);
+        }
+
+        let segment_size = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        if segment_size == 0 || segment_size > MAX_SEGMENT_SIZE {
+            return Err(Error::InvalidSegmentSize);
+        }
+
+        Ok(FIXED_SIZE + SALT_SIZE + algorithm.nonce_prefix_size() + kdf_len + KEY_CHECK_SIZE + HEADER_TAG_SIZE)
+    }
+
+    /// Check the key against a parsed header, then authenticate the header
+    ///
+    /// ## Returns
+    /// `Error::WrongKey` if the key check value does not match, or `Error::InvalidHeaderTag` if
+    /// the header was modified
+    pub fn verify(&self, key: &[u8; 32]) -> Result<(), Error> {
+        self.file_keys(key).map(|_| ())
+    }
+
+    fn file_keys(&self, key: &[u8; 32]) -> Result<FileKeys, Error> {
+        let keys = FileKeys::derive(key, &self.salt);
+        if !constant_time_eq(&keys.key_check, &self.key_check) {
+            return Err(Error::WrongKey);
+        }
+
+        let mut bytes = self.encode_fields();
+        bytes.extend_from_slice(&self.key_check);
+        let mut mac = Poly1305::new(&keys.mac_key);
+        mac.update(&bytes);
+        mac.verify(&self.tag).map_err(|_| Error::InvalidHeaderTag)?;
+
+        Ok(keys)
+    }
+
+    fn kdf_params(&self) -> &[u8] {
+        self.kdf.as_ref().map_or(&[], |kdf| kdf.params())
+    }
+
+    /// Encode every field before the key check value
+    fn encode_fields(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.algorithm.id());
+        bytes.push(self.kdf.as_ref().map_or(0, |kdf| kdf.id()));
+        bytes.push(self.kdf_params().len() as u8);
+        bytes.extend_from_slice(&self.segment_size.to_le_bytes());
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(self.nonce_prefix());
+        bytes.extend_from_slice(self.kdf_params());
+        bytes
+    }
+}
+
+/// Split the first `len` bytes off `bytes`, which the caller has checked are there
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> &'a [u8] {
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    head
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && core::hint::black_box(a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y))) == 0
+}
+
+#[cfg(feature = "std")]
+mod adapters {
+    use super::{Algorithm, Error, FileKeys, Header, FIXED_SIZE};
+    use crate::stream::{OpeningReader, SealingWriter, StreamDecryptor, StreamEncryptor, NONCE_PREFIX_SIZE};
+    use std::io::{self, Read, Write};
+    use std::vec::Vec;
+
+    fn encryptor(header: &Header, keys: &FileKeys) -> StreamEncryptor {
+        match header.algorithm {
+            Algorithm::ChaCha20Poly1305 => {
+                let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+                prefix.copy_from_slice(header.nonce_prefix());
+                StreamEncryptor::new(&keys.segment_key, &prefix)
+            }
+            Algorithm::XChaCha20Poly1305 => StreamEncryptor::new_x(&keys.segment_key, &header.nonce),
+        }
+    }
+
+    fn decryptor(header: &Header, keys: &FileKeys) -> StreamDecryptor {
+        match header.algorithm {
+            Algorithm::ChaCha20Poly1305 => {
+                let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+                prefix.copy_from_slice(header.nonce_prefix());
+                StreamDecryptor::new(&keys.segment_key, &prefix)
+            }
+            Algorithm::XChaCha20Poly1305 => StreamDecryptor::new_x(&keys.segment_key, &header.nonce),
+        }
+    }
+
+    fn invalid_data(error: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+
+    /// Writer that writes a container header and then encrypts everything written to it
+    ///
+    /// [`ContainerWriter::finish`] must be called to end the container.
+    pub struct ContainerWriter<W: Write> {
+        writer: SealingWriter<W>,
+    }
+
+    impl<W: Write> ContainerWriter<W> {
+        /// Write the header for `key` and start the encrypted stream
+        pub fn new(mut inner: W, key: &[u8; 32], header: &Header) -> io::Result<Self> {
+            let keys = FileKeys::derive(key, &header.salt);
+            inner.write_all(&header.encode_with(&keys))?;
+
+            let writer = SealingWriter::new(inner, encryptor(header, &keys)).with_segment_size(header.segment_size as usize);
+            Ok(ContainerWriter { writer })
+        }
+
+        /// Write the last segment and flush
+        ///
+        /// ## Returns
+        /// The inner writer
+        pub fn finish(self) -> io::Result<W> {
+            self.writer.finish()
+        }
+    }
+
+    impl<W: Write> Write for ContainerWriter<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writer.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.writer.flush()
+        }
+    }
+
+    /// Read a container header from `inner`, consuming exactly its bytes
+    ///
+    /// ## Returns
+    /// The header, or an `InvalidData` error wrapping an [`Error`] if it is malformed
+    pub fn read_header<R: Read>(inner: &mut R) -> io::Result<Header> {
+        let mut bytes = Vec::new();
+        let len = read_until(inner, &mut bytes, FIXED_SIZE)
+            .and_then(|()| Header::parse_len(&bytes).map_err(invalid_data))?;
+        read_until(inner, &mut bytes, len)?;
+
+        let (header, _) = Header::parse(&bytes).map_err(invalid_data)?;
+        Ok(header)
+    }
+
+    /// Read until `bytes` holds `len` bytes, failing with the parse error once the input ends
+    fn read_until<R: Read>(inner: &mut R, bytes: &mut Vec<u8>, len: usize) -> io::Result<()> {
+        let start = bytes.len();
+        inner.take((len - start) as u64).read_to_end(bytes)?;
+        if bytes.len() < len {
+            let error = Header::parse_len(bytes).err().unwrap_or(Error::Truncated);
+            return Err(invalid_data(error));
+        }
+
+        Ok(())
+    }
+
+    /// Reader that checks the key against a container header and decrypts the segments after it
+    pub struct ContainerReader<R: Read> {
+        header: Header,
+        reader: OpeningReader<R>,
+    }
+
+    impl<R: Read> ContainerReader<R> {
+        /// Read the header and check the key, before any segment is decrypted
+        ///
+        /// ## Returns
+        /// An `InvalidData` error wrapping [`Error::WrongKey`] for a wrong key, or another
+        /// [`Error`] for a malformed or modified header
+        pub fn new(mut inner: R, key: &[u8; 32]) -> io::Result<Self> {
+            let header = read_header(&mut inner)?;
+            Self::with_header(inner, key, header)
+        }
+
+        /// Start decrypting the segments after a header that was already read with
+        /// [`read_header`], for example to derive the key from its KDF parameters
+        pub fn with_header(inner: R, key: &[u8; 32], header: Header) -> io::Result<Self> {
+            let keys = header.file_keys(key).map_err(invalid_data)?;
+            let reader =
+                OpeningReader::new(inner, decryptor(&header, &keys)).with_segment_size(header.segment_size as usize);
+            Ok(ContainerReader { header, reader })
+        }
+
+        /// Get the header
+        pub fn header(&self) -> &Header {
+            &self.header
+        }
+
+        /// Unwrap the inner reader
+        pub fn into_inner(self) -> R {
+            self.reader.into_inner()
+        }
+    }
+
+    impl<R: Read> Read for ContainerReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reader.read(buf)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use adapters::{read_header, ContainerReader, ContainerWriter};
@@ -0,0 +1,217 @@
+//! # Fast-key-erasure random generator
+//!
+//! A generator with forward secrecy, following Bernstein's "fast-key-erasure" design. Each time
+//! the output buffer is refilled, ChaCha20 under the current key produces [`BUFFER_SIZE`] bytes:
+//! the first 32 bytes immediately replace the key and the rest becomes output. Output is wiped
+//! from the buffer as soon as it is handed out. The state therefore only determines future
+//! output, and an attacker who captures it cannot recompute anything returned earlier.
+//!
+//! The generator is periodically reseeded from an injected [`EntropySource`], which mixes fresh
+//! entropy into the key and discards the buffered output.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::fast_key_erasure::{Error, FastKeyErasureRng};
+//!
+//! // Use the operating system's generator in real code
+//! let entropy = |dest: &mut [u8]| -> Result<(), Error> {
+//!     dest.fill(0x42);
+//!     Ok(())
+//! };
+//!
+//! let mut rng = FastKeyErasureRng::new(entropy).unwrap().with_reseed_interval(1 << 16);
+//! let key = rng.gen_key().unwrap();
+//! println!("Key: {:?}", key);
+//! ```
+
+use crate::chacha20::{ChaCha20, ChaCha20Impl};
+use crate::wipe;
+use core::fmt;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Bytes of keystream generated per refill, including the 32 bytes of the next key
+pub const BUFFER_SIZE: usize = 768;
+
+/// Default number of output bytes between two reseeds
+pub const DEFAULT_RESEED_INTERVAL: u64 = 1 << 20;
+
+/// Nonce of the keystream used to mix entropy into the key, separate from the output nonce
+const RESEED_NONCE: [u8; 12] = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// Errors returned by the fast-key-erasure generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The entropy source could not provide entropy
+    EntropyUnavailable,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EntropyUnavailable => write!(f, "Entropy source is unavailable"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Source of the entropy used to seed and reseed the generator
+///
+/// Implemented for closures, so a system generator can be plugged in with a one-line adapter.
+pub trait EntropySource {
+    /// Fill `dest` with fresh entropy
+    fn fill_entropy(&mut self, dest: &mut [u8]) -> Result<(), Error>;
+}
+
+impl<F: FnMut(&mut [u8]) -> Result<(), Error>> EntropySource for F {
+    fn fill_entropy(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self(dest)
+    }
+}
+
+/// Fast-key-erasure generator reseeded from the entropy source `E`
+pub struct FastKeyErasureRng<E: EntropySource> {
+    key: [u8; 32],
+    buffer: [u8; BUFFER_SIZE],
+    /// Index of the next unused byte in `buffer`, `BUFFER_SIZE` when it is used up
+    position: usize,
+    since_reseed: u64,
+    reseed_interval: u64,
+    entropy: E,
+}
+
+impl<E: EntropySource> FastKeyErasureRng<E> {
+    /// Create a generator seeded from the entropy source
+    ///
+    /// ## Returns
+    /// `Error::EntropyUnavailable` if the source fails
+    pub fn new(mut entropy: E) -> Result<Self, Error> {
+        let mut seed = [0u8; 32];
+        if let Err(error) = entropy.fill_entropy(&mut seed) {
+            wipe(&mut seed);
+            return Err(error);
+        }
+        let rng = Self::from_seed(seed, entropy);
+
+        wipe(&mut seed);
+
+        Ok(rng)
+    }
+
+    /// Create a generator with a known seed, which is reseeded from `entropy` later on
+    ///
+    /// Until the first reseed the output is fully determined by `seed`.
+    pub fn from_seed(seed: [u8; 32], entropy: E) -> Self {
+        FastKeyErasureRng {
+            key: seed,
+            buffer: [0u8; BUFFER_SIZE],
+            position: BUFFER_SIZE,
+            since_reseed: 0,
+            reseed_interval: DEFAULT_RESEED_INTERVAL,
+            entropy,
+        }
+    }
+
+    /// Set the number of output bytes after which the generator reseeds
+    pub fn with_reseed_interval(mut self, bytes: u64) -> Self {
+        self.reseed_interval = bytes;
+        self
+    }
+
+    /// Mix fresh entropy into the key and discard the buffered output
+    ///
+    /// The new key is 32 bytes of keystream under the old key XORed with 32 bytes of entropy, so
+    /// it stays unpredictable as long as either of them is.
+    ///
+    /// ## Returns
+    /// `Error::EntropyUnavailable` if the source fails, in which case the state is unchanged
+    pub fn reseed(&mut self) -> Result<(), Error> {
+        let mut entropy = [0u8; 32];
+        if let Err(error) = self.entropy.fill_entropy(&mut entropy) {
+            // A failing source may still have written part of the buffer
+            wipe(&mut entropy);
+            return Err(error);
+        }
+
+        let mut cipher = ChaCha20Impl::from_key_nonce(&self.key, &RESEED_NONCE);
+        cipher.apply_keystream(&mut entropy);
+        self.key = entropy;
+
+        self.buffer.fill(0);
+        self.position = BUFFER_SIZE;
+        self.since_reseed = 0;
+
+        wipe(&mut entropy);
+
+        Ok(())
+    }
+
+    /// Fill `dest` with random bytes
+    ///
+    /// The generator is reseeded whenever the reseed interval is reached, also in the middle of
+    /// `dest`.
+    ///
+    /// ## Returns
+    /// `Error::EntropyUnavailable` if a due reseed fails. The bytes of `dest` before that point
+    /// have already been written, so none are if the reseed was due at the start
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut filled = 0;
+        while filled < dest.len() {
+            if self.since_reseed >= self.reseed_interval {
+                self.reseed()?;
+            }
+            if self.position == BUFFER_SIZE {
+                self.refill();
+            }
+
+            let until_reseed = (self.reseed_interval - self.since_reseed).max(1);
+            let len = (dest.len() - filled)
+                .min(BUFFER_SIZE - self.position)
+                .min(usize::try_from(until_reseed).unwrap_or(usize::MAX));
+            let output = &mut self.buffer[self.position..self.position + len];
+            dest[filled..filled + len].copy_from_slice(output);
+            // Erase the output as soon as it is handed out
+            output.fill(0);
+
+            self.position += len;
+            self.since_reseed += len as u64;
+            filled += len;
+        }
+
+        Ok(())
+    }
+
+    /// Generate a 256-bit key
+    ///
+    /// ## Returns
+    /// `Error::EntropyUnavailable` if a due reseed fails
+    pub fn gen_key(&mut self) -> Result<[u8; 32], Error> {
+        let mut key = [0u8; 32];
+        self.fill_bytes(&mut key)?;
+        Ok(key)
+    }
+
+    /// Replace the key with the start of a fresh keystream and buffer the rest as output
+    fn refill(&mut self) {
+        self.buffer.fill(0);
+        let mut cipher = ChaCha20Impl::from_key_nonce(&self.key, &[0u8; 12]);
+        cipher.apply_keystream(&mut self.buffer);
+
+        self.key.copy_from_slice(&self.buffer[..32]);
+        self.buffer[..32].fill(0);
+        self.position = 32;
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<E: EntropySource> Drop for FastKeyErasureRng<E> {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.buffer.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<E: EntropySource> ZeroizeOnDrop for FastKeyErasureRng<E> {}
@@ -0,0 +1,226 @@
+//! # `std::io` adapters
+//!
+//! Wrappers that apply the keystream to everything passing through a reader or writer:
+//! - [`EncryptingWriter`] encrypts what is written to it before handing it to the inner writer
+//! - [`DecryptingReader`] decrypts what it reads from the inner reader
+//! - [`StreamingReader`] additionally implements [`Seek`], moving the cipher along with the inner
+//!   reader so that any part of the stream can be decrypted on its own
+//!
+//! The keystream is XORed in both directions, so the writer decrypts and the readers encrypt just
+//! as well. Short reads and writes are handled by keeping the cipher at the position of the last
+//! byte that actually went through.
+//!
+//! Available with the `std` feature.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+//! use chacha20imp::io::{DecryptingReader, EncryptingWriter};
+//! use std::io::{Read, Write};
+//!
+//! let key = [0x42u8; 32];
+//! let nonce = [0x24u8; 12];
+//!
+//! let mut writer = EncryptingWriter::new(Vec::new(), ChaCha20Impl::new(&key, &nonce));
+//! writer.write_all(b"Hello, world!").unwrap();
+//! let ciphertext = writer.into_inner();
+//!
+//! let mut reader = DecryptingReader::new(&ciphertext[..], ChaCha20Impl::new(&key, &nonce));
+//! let mut plaintext = String::new();
+//! reader.read_to_string(&mut plaintext).unwrap();
+//! assert_eq!(plaintext, "Hello, world!");
+//! ```
+
+use crate::chacha20::{ChaCha20, ChaCha20Impl, Error};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Largest amount of data encrypted by a single call to [`EncryptingWriter::write`]
+pub const CHUNK_SIZE: usize = 4096;
+
+pub(crate) fn keystream_error(error: Error) -> io::Error {
+    io::Error::other(error)
+}
+
+/// Apply the keystream to the data just read, failing instead of wrapping the block counter
+pub(crate) fn decrypt<C: ChaCha20>(cipher: &mut C, buf: &mut [u8]) -> io::Result<()> {
+    cipher.try_apply_keystream(buf).map_err(keystream_error)
+}
+
+/// Writer that encrypts data before writing it to the inner writer
+pub struct EncryptingWriter<W: Write, C: ChaCha20 = ChaCha20Impl> {
+    inner: W,
+    cipher: C,
+}
+
+impl<W: Write, C: ChaCha20> EncryptingWriter<W, C> {
+    /// Wrap `inner`, encrypting with `cipher` from its current position
+    pub fn new(inner: W, cipher: C) -> Self {
+        EncryptingWriter { inner, cipher }
+    }
+
+    /// Get a reference to the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner writer
+    ///
+    /// Writing to it directly desynchronises the ciphertext from the keystream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Get a reference to the cipher
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    /// Unwrap the inner writer
+    ///
+    /// Nothing is buffered, so every byte accepted by `write` has already reached it.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write, C: ChaCha20> Write for EncryptingWriter<W, C> {
+    /// Encrypt and write up to [`CHUNK_SIZE`] bytes of `buf`
+    ///
+    /// When the inner writer takes fewer bytes, the cipher is moved back to the first byte that
+    /// was not written, so it is encrypted again by the next call.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(CHUNK_SIZE);
+        let mut chunk = [0u8; CHUNK_SIZE];
+        chunk[..len].copy_from_slice(&buf[..len]);
+
+        let pos = self.cipher.current_pos();
+        self.cipher.try_apply_keystream(&mut chunk[..len]).map_err(keystream_error)?;
+
+        match self.inner.write(&chunk[..len]) {
+            Ok(written) => {
+                if written < len {
+                    self.cipher.seek_pos(pos + written as u64).map_err(keystream_error)?;
+                }
+                Ok(written)
+            }
+            Err(error) => {
+                self.cipher.seek_pos(pos).map_err(keystream_error)?;
+                Err(error)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that decrypts data read from the inner reader
+pub struct DecryptingReader<R: Read, C: ChaCha20 = ChaCha20Impl> {
+    inner: R,
+    cipher: C,
+}
+
+impl<R: Read, C: ChaCha20> DecryptingReader<R, C> {
+    /// Wrap `inner`, decrypting with `cipher` from its current position
+    pub fn new(inner: R, cipher: C) -> Self {
+        DecryptingReader { inner, cipher }
+    }
+
+    /// Get a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader
+    ///
+    /// Reading from it directly desynchronises the ciphertext from the keystream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get a reference to the cipher
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, C: ChaCha20> Read for DecryptingReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        decrypt(&mut self.cipher, &mut buf[..read])?;
+        Ok(read)
+    }
+}
+
+/// Seekable reader that decrypts data read from the inner reader
+///
+/// Byte `n` of the inner reader is decrypted with byte `n` of the keystream, so the ciphertext
+/// is expected to start at offset 0. Seeking moves both the inner reader and the cipher, which
+/// maps the new offset to a block counter and an offset within that block.
+pub struct StreamingReader<R: Read + Seek, C: ChaCha20 = ChaCha20Impl> {
+    inner: R,
+    cipher: C,
+}
+
+impl<R: Read + Seek, C: ChaCha20> StreamingReader<R, C> {
+    /// Wrap `inner`, which must be at the same position as `cipher`
+    pub fn new(inner: R, cipher: C) -> Self {
+        StreamingReader { inner, cipher }
+    }
+
+    /// Get a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader
+    ///
+    /// Reading from it or seeking it directly desynchronises the ciphertext from the keystream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get a reference to the cipher
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek, C: ChaCha20> Read for StreamingReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        decrypt(&mut self.cipher, &mut buf[..read])?;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek, C: ChaCha20> Seek for StreamingReader<R, C> {
+    /// Seek the inner reader and move the cipher to the new offset
+    ///
+    /// Fails with `InvalidInput` when the offset is past the end of the keystream, in which case
+    /// the inner reader is moved back to where it was.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let offset = self.inner.seek(pos)?;
+        if let Err(error) = self.cipher.seek_pos(offset) {
+            self.inner.seek(SeekFrom::Start(self.cipher.current_pos()))?;
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+        }
+
+        Ok(offset)
+    }
+}
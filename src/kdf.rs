@@ -0,0 +1,467 @@
+//! # Password-based key derivation
+//!
+//! Turns a password into a 256-bit [`Key`] with a memory-hard function, so that every password
+//! guess costs an attacker the same time and memory as a legitimate derivation:
+//! - Argon2id (RFC 9106), the default
+//! - scrypt (RFC 7914)
+//!
+//! The salt must be random and unique per derivation. A container already has one, so the usual
+//! pattern is to derive the key from the password and [`Header::salt`], and to store the
+//! parameters in the header with [`Header::with_kdf`]. [`Kdf`] converts to and from the header's
+//! [`KdfParams`] block:
+//! - Argon2id, identifier 1: memory in KiB, iterations and parallelism as little-endian `u32`
+//! - scrypt, identifier 2: log2 of `N` as one byte, then `r` and `p` as little-endian `u32`
+//!
+//! Parameters read from a header are untrusted and are used before the header tag can be checked,
+//! so a crafted file could otherwise make a derivation run for hours. Decoding them checks the
+//! memory, passes and lanes against [`Limits`], by default 1 GiB, 10 passes and 16 lanes; use
+//! [`Kdf::from_params`] to accept more.
+//!
+//! Available with the `kdf` feature.
+//!
+//! [`Header::salt`]: crate::container::Header::salt
+//! [`Header::with_kdf`]: crate::container::Header::with_kdf
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::container::{Algorithm, Header};
+//! use chacha20imp::kdf::{derive_key, Argon2Params, Kdf};
+//!
+//! // Use random bytes from a CSPRNG for the salt and the nonce, and a higher cost than this
+//! let kdf = Kdf::Argon2id(Argon2Params::new(64, 1, 1).unwrap());
+//! let header = Header::new(Algorithm::XChaCha20Poly1305, [1u8; 24], [2u8; 19]).with_kdf(kdf.into());
+//! let key = derive_key(b"correct horse battery staple", header.salt(), &kdf).unwrap();
+//! let bytes = header.encode(key.as_bytes());
+//!
+//! // Reading it back takes the parameters from the header
+//! let (header, _) = Header::parse(&bytes).unwrap();
+//! let kdf = Kdf::try_from(header.kdf().unwrap()).unwrap();
+//! let key = derive_key(b"correct horse battery staple", header.salt(), &kdf).unwrap();
+//! assert_eq!(header.verify(key.as_bytes()), Ok(()));
+//! ```
+
+use crate::container::KdfParams;
+#[cfg(feature = "rand_core")]
+use crate::container::SALT_SIZE;
+use core::fmt;
+#[cfg(feature = "rand_core")]
+use rand_core::{CryptoRng, RngCore};
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Largest accepted memory cost, 4 GiB
+pub const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+/// Default memory limit for parameters read from a header, 1 GiB
+pub const DEFAULT_MAX_MEMORY_KIB: u32 = 1024 * 1024;
+
+/// Default limit on the Argon2id passes read from a header
+pub const DEFAULT_MAX_ITERATIONS: u32 = 10;
+
+/// Default limit on the Argon2id lanes and the scrypt `p` read from a header
+pub const DEFAULT_MAX_PARALLELISM: u32 = 16;
+
+/// KDF identifier of Argon2id in a container header
+const ARGON2ID_ID: u8 = 1;
+
+/// KDF identifier of scrypt in a container header
+const SCRYPT_ID: u8 = 2;
+
+/// Errors returned by the password KDFs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The cost parameters are out of range, or the encoded parameters are malformed
+    InvalidParams,
+    /// The KDF identifier is not a known KDF
+    UnknownKdf,
+    /// The salt is shorter than the 8 bytes Argon2 requires
+    SaltTooShort,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidParams => write!(f, "Invalid KDF parameters"),
+            Error::UnknownKdf => write!(f, "Unknown KDF"),
+            Error::SaltTooShort => write!(f, "Salt is too short"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// A 256-bit key derived from a password
+///
+/// Pass [`Key::as_bytes`] to the ciphers, the AEADs or the container.
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Get the key bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Key {
+    /// Never prints the key
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for Key {}
+
+/// Argon2id cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Argon2Params {
+    /// Create Argon2id parameters
+    ///
+    /// ## Arguments
+    /// - `memory_kib` - Memory cost in KiB, at least 8 per lane and at most [`MAX_MEMORY_KIB`]
+    /// - `iterations` - Number of passes over the memory, at least 1
+    /// - `parallelism` - Number of lanes, between 1 and 2^24 - 1
+    ///
+    /// ## Returns
+    /// `Error::InvalidParams` if a parameter is out of range
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, Error> {
+        if memory_kib > MAX_MEMORY_KIB {
+            return Err(Error::InvalidParams);
+        }
+        argon2::Params::new(memory_kib, iterations, parallelism, Some(32)).map_err(|_| Error::InvalidParams)?;
+
+        Ok(Argon2Params {
+            memory_kib,
+            iterations,
+            parallelism,
+        })
+    }
+
+    /// Get the memory cost in KiB
+    pub fn memory_kib(&self) -> u32 {
+        self.memory_kib
+    }
+
+    /// Get the number of passes
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Get the number of lanes
+    pub fn parallelism(&self) -> u32 {
+        self.parallelism
+    }
+
+    /// Derive a key with Argon2id
+    ///
+    /// ## Arguments
+    /// - `password` - The password
+    /// - `salt` - Random bytes, at least 8
+    pub fn derive_key(&self, password: &[u8], salt: &[u8]) -> Result<Key, Error> {
+        self.derive_key_with_secret(password, salt, &[], &[])
+    }
+
+    /// Derive a key with Argon2id and its optional inputs
+    ///
+    /// ## Arguments
+    /// - `password` - The password
+    /// - `salt` - Random bytes, at least 8
+    /// - `secret` - A secret key kept apart from the stored data, or empty
+    /// - `associated_data` - Up to 32 bytes of context, or empty
+    pub fn derive_key_with_secret(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        secret: &[u8],
+        associated_data: &[u8],
+    ) -> Result<Key, Error> {
+        let data = argon2::AssociatedData::new(associated_data).map_err(|_| Error::InvalidParams)?;
+        let params = argon2::ParamsBuilder::new()
+            .m_cost(self.memory_kib)
+            .t_cost(self.iterations)
+            .p_cost(self.parallelism)
+            .data(data)
+            .output_len(32)
+            .build()
+            .map_err(|_| Error::InvalidParams)?;
+        let argon2 = if secret.is_empty() {
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+        } else {
+            argon2::Argon2::new_with_secret(secret, argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                .map_err(|_| Error::InvalidParams)?
+        };
+
+        let mut key = Key([0u8; 32]);
+        argon2.hash_password_into(password, salt, &mut key.0).map_err(|error| match error {
+            argon2::Error::SaltTooShort => Error::SaltTooShort,
+            _ => Error::InvalidParams,
+        })?;
+        Ok(key)
+    }
+}
+
+impl Default for Argon2Params {
+    /// 19 MiB, 2 passes and 1 lane, the OWASP recommendation for Argon2id
+    fn default() -> Self {
+        Argon2Params {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// scrypt cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl ScryptParams {
+    /// Create scrypt parameters
+    ///
+    /// ## Arguments
+    /// - `log_n` - log2 of the CPU/memory cost `N`, below `16 * r`
+    /// - `r` - Block size, at least 1
+    /// - `p` - Parallelization, at least 1 and with `r * p` below 2^30
+    ///
+    /// ## Returns
+    /// `Error::InvalidParams` if a parameter is out of range or `128 * r * N` bytes is more than
+    /// [`MAX_MEMORY_KIB`]
+    pub fn new(log_n: u8, r: u32, p: u32) -> Result<Self, Error> {
+        if log_n >= 64 || (128u128 * r as u128) << log_n > MAX_MEMORY_KIB as u128 * 1024 {
+            return Err(Error::InvalidParams);
+        }
+        scrypt::Params::new(log_n, r, p, 32).map_err(|_| Error::InvalidParams)?;
+
+        Ok(ScryptParams { log_n, r, p })
+    }
+
+    /// Get log2 of `N`
+    pub fn log_n(&self) -> u8 {
+        self.log_n
+    }
+
+    /// Get the block size
+    pub fn r(&self) -> u32 {
+        self.r
+    }
+
+    /// Get the parallelization
+    pub fn p(&self) -> u32 {
+        self.p
+    }
+
+    /// Derive a key with scrypt
+    pub fn derive_key(&self, password: &[u8], salt: &[u8]) -> Result<Key, Error> {
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, 32).map_err(|_| Error::InvalidParams)?;
+
+        let mut key = Key([0u8; 32]);
+        scrypt::scrypt(password, salt, &params, &mut key.0).map_err(|_| Error::InvalidParams)?;
+        Ok(key)
+    }
+}
+
+impl Default for ScryptParams {
+    /// `N` = 2^17, `r` = 8 and `p` = 1, 128 MiB and the OWASP recommendation for scrypt
+    fn default() -> Self {
+        ScryptParams { log_n: 17, r: 8, p: 1 }
+    }
+}
+
+/// Upper bounds on the cost of parameters read from a container header
+///
+/// scrypt runs its `p` mixing rounds one after another, so `p` counts against the parallelism
+/// limit like the Argon2id lanes, and `128 * r * N` bytes against the memory limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    max_memory_kib: u32,
+    max_iterations: u32,
+    max_parallelism: u32,
+}
+
+impl Limits {
+    /// Create limits
+    ///
+    /// ## Arguments
+    /// - `max_memory_kib` - Largest memory cost in KiB
+    /// - `max_iterations` - Largest number of Argon2id passes
+    /// - `max_parallelism` - Largest number of Argon2id lanes and scrypt `p`
+    pub fn new(max_memory_kib: u32, max_iterations: u32, max_parallelism: u32) -> Self {
+        Limits {
+            max_memory_kib,
+            max_iterations,
+            max_parallelism,
+        }
+    }
+
+    /// Check that `kdf` stays within the limits
+    ///
+    /// ## Returns
+    /// `Error::InvalidParams` if a cost is above its limit
+    pub fn check(&self, kdf: &Kdf) -> Result<(), Error> {
+        let within = match kdf {
+            Kdf::Argon2id(argon2) => {
+                argon2.memory_kib <= self.max_memory_kib
+                    && argon2.iterations <= self.max_iterations
+                    && argon2.parallelism <= self.max_parallelism
+            }
+            Kdf::Scrypt(scrypt) => {
+                (128u128 * scrypt.r as u128) << scrypt.log_n <= self.max_memory_kib as u128 * 1024
+                    && scrypt.p <= self.max_parallelism
+            }
+        };
+
+        if within {
+            Ok(())
+        } else {
+            Err(Error::InvalidParams)
+        }
+    }
+}
+
+impl Default for Limits {
+    /// [`DEFAULT_MAX_MEMORY_KIB`], [`DEFAULT_MAX_ITERATIONS`] and [`DEFAULT_MAX_PARALLELISM`]
+    fn default() -> Self {
+        Limits::new(DEFAULT_MAX_MEMORY_KIB, DEFAULT_MAX_ITERATIONS, DEFAULT_MAX_PARALLELISM)
+    }
+}
+
+/// A password KDF with its cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// Argon2id
+    Argon2id(Argon2Params),
+    /// scrypt
+    Scrypt(ScryptParams),
+}
+
+impl Kdf {
+    /// Decode and validate the parameters stored in a container header, within `limits`
+    ///
+    /// `Kdf::try_from` does the same with the default [`Limits`].
+    ///
+    /// ## Returns
+    /// `Error::UnknownKdf` for an unknown identifier, or `Error::InvalidParams` if the parameters
+    /// are malformed, out of range or above the limits
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::container::KdfParams;
+    /// use chacha20imp::kdf::{Argon2Params, Error, Kdf, Limits};
+    ///
+    /// // 2 GiB, above the default limit of 1 GiB
+    /// let kdf = Kdf::Argon2id(Argon2Params::new(2 * 1024 * 1024, 1, 4).unwrap());
+    /// let params: KdfParams = kdf.into();
+    /// assert_eq!(Kdf::try_from(&params), Err(Error::InvalidParams));
+    /// assert_eq!(Kdf::from_params(&params, &Limits::new(2 * 1024 * 1024, 1, 4)), Ok(kdf));
+    /// ```
+    pub fn from_params(kdf: &KdfParams, limits: &Limits) -> Result<Self, Error> {
+        let params = kdf.params();
+        let word = |i: usize| u32::from_le_bytes([params[i], params[i + 1], params[i + 2], params[i + 3]]);
+
+        let decoded = match (kdf.id(), params.len()) {
+            (ARGON2ID_ID, 12) => Argon2Params::new(word(0), word(4), word(8)).map(Kdf::Argon2id),
+            (SCRYPT_ID, 9) => ScryptParams::new(params[0], word(1), word(5)).map(Kdf::Scrypt),
+            (ARGON2ID_ID | SCRYPT_ID, _) => Err(Error::InvalidParams),
+            _ => Err(Error::UnknownKdf),
+        }?;
+        limits.check(&decoded)?;
+
+        Ok(decoded)
+    }
+}
+
+impl Default for Kdf {
+    /// Argon2id with its default parameters
+    fn default() -> Self {
+        Kdf::Argon2id(Argon2Params::default())
+    }
+}
+
+impl From<Kdf> for KdfParams {
+    fn from(kdf: Kdf) -> Self {
+        let mut params = [0u8; 12];
+        let (id, len) = match kdf {
+            Kdf::Argon2id(argon2) => {
+                params[..4].copy_from_slice(&argon2.memory_kib.to_le_bytes());
+                params[4..8].copy_from_slice(&argon2.iterations.to_le_bytes());
+                params[8..].copy_from_slice(&argon2.parallelism.to_le_bytes());
+                (ARGON2ID_ID, 12)
+            }
+            Kdf::Scrypt(scrypt) => {
+                params[0] = scrypt.log_n;
+                params[1..5].copy_from_slice(&scrypt.r.to_le_bytes());
+                params[5..9].copy_from_slice(&scrypt.p.to_le_bytes());
+                (SCRYPT_ID, 9)
+            }
+        };
+
+        KdfParams::new(id, &params[..len]).expect("KDF parameters fit in the header")
+    }
+}
+
+impl TryFrom<&KdfParams> for Kdf {
+    type Error = Error;
+
+    /// Decode and validate the parameters stored in a container header, within the default
+    /// [`Limits`]
+    fn try_from(kdf: &KdfParams) -> Result<Self, Error> {
+        Kdf::from_params(kdf, &Limits::default())
+    }
+}
+
+/// Derive a key from a password
+///
+/// ## Arguments
+/// - `password` - The password
+/// - `salt` - Random bytes, unique per derivation; at least 8 for Argon2id
+/// - `kdf` - The KDF and its cost parameters
+///
+/// ## Returns
+/// The key, or `Error::SaltTooShort` if the salt is too short for Argon2id
+///
+/// ## Example
+/// ```
+/// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+/// use chacha20imp::chacha20poly1305::ChaCha20Poly1305;
+/// use chacha20imp::kdf::{derive_key, Kdf, ScryptParams};
+///
+/// let kdf = Kdf::Scrypt(ScryptParams::new(10, 8, 1).unwrap());
+/// let key = derive_key(b"password", b"random salt", &kdf).unwrap();
+///
+/// let mut cipher = ChaCha20Impl::from_key_nonce(key.as_bytes(), &[0u8; 12]);
+/// let aead = ChaCha20Poly1305::new(key.as_bytes());
+/// ```
+pub fn derive_key(password: &[u8], salt: &[u8], kdf: &Kdf) -> Result<Key, Error> {
+    match kdf {
+        Kdf::Argon2id(argon2) => argon2.derive_key(password, salt),
+        Kdf::Scrypt(scrypt) => scrypt.derive_key(password, salt),
+    }
+}
+
+/// Generate a random salt of the size used by the container
+///
+/// Available with the `rand_core` feature.
+#[cfg(feature = "rand_core")]
+pub fn generate_salt<R: RngCore + CryptoRng>(rng: &mut R) -> [u8; SALT_SIZE] {
+    let mut salt = [0u8; SALT_SIZE];
+    rng.fill_bytes(&mut salt);
+    salt
+}
@@ -7,6 +7,18 @@
 //! - Keystream generation
 //! - Seek to a specific position in the keystream
 //! - Support for customizable keys and nonces
+//! - ChaCha8 and ChaCha12 reduced-round variants from the same core
+//! - Optional wiping of key material on drop with the `zeroize` feature
+//! - Original ChaCha20 layout with a 64-bit nonce and 64-bit block counter
+//! - ChaCha20-Poly1305 authenticated encryption (RFC 8439)
+//! - Standalone Poly1305 message authenticator
+//! - XChaCha20 with 192-bit nonces via HChaCha20, and the matching XChaCha20-Poly1305 AEAD
+//! - SSE2 and AVX2 backends computing several blocks at once, selected at runtime
+//! - Streaming AEAD that splits long messages into authenticated segments (STREAM construction)
+//! - Versioned container format with an authenticated header and a key check value
+//! - Password-based keys with Argon2id or scrypt, with the cost parameters kept in the container
+//! - Fast-key-erasure random generator with forward secrecy and periodic reseeding
+//! - `no_std` support, with the allocating AEAD API behind the default `alloc` feature
 //!
 //! ## Example
 //! ```rust
@@ -20,23 +32,256 @@
 //! println!("Keystream: {:?}", buf);
 //! ```
 //! For more usage examples, check the `examples/` directory in the repository.
+//!
+//! ## Cargo features
+//! - `alloc` (default) - `Vec`-returning `encrypt`/`decrypt` on the AEADs and the `container`
+//!   header
+//! - `std` - implements `std::error::Error` for the error types and adds the `io` reader and
+//!   writer adapters and the `stream` and `container` readers and writers; implies `alloc`
+//! - `zeroize` - wipes key material on drop
+//! - `rayon` - parallel keystream application for large buffers; implies `std`
+//! - `cipher` - implements the RustCrypto `KeyIvInit`, `StreamCipher` and `StreamCipherSeek`
+//!   traits and re-exports the `cipher` crate
+//! - `rand_core` - `rng::ChaChaRng`, a seedable CSPRNG compatible with `rand_chacha`, and
+//!   re-exports the `rand_core` crate
+//! - `tokio` - `AsyncRead`/`AsyncWrite` adapters in `async_io` for tokio; implies `std`
+//! - `futures-io` - the same adapters for the `futures-io` traits; implies `std`
+//! - `kdf` - password-based key derivation with Argon2id or scrypt in `kdf`; implies `alloc`
+//! - `cli` - builds the `chacha20imp` command-line tool; implies `std`, `zeroize` and `kdf`
+//!
+//! With `default-features = false` the crate only needs `core` and builds for bare-metal targets
+//! such as `thumbv7em-none-eabi`.
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+/// Overwrite a temporary copy of key material; does nothing without the `zeroize` feature
+#[cfg(feature = "zeroize")]
+pub(crate) fn wipe<T: zeroize::Zeroize + ?Sized>(secret: &mut T) {
+    secret.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn wipe<T: ?Sized>(_secret: &mut T) {}
 
 pub mod chacha20 {
-    pub trait ChaCha20 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    use crate::backends::{avx2, sse2};
+    use crate::backends::{soft, Backend};
+    use crate::wipe;
+    use core::fmt;
+    #[cfg(feature = "zeroize")]
+    use zeroize::{Zeroize, ZeroizeOnDrop};
+
+    pub trait ChaCha20: Sized {
+        /// Key length in bytes
+        const KEY_SIZE: usize = 32;
+        /// Nonce length in bytes
+        const NONCE_SIZE: usize;
+
         fn new(key: &[u8], nonce: &[u8]) -> Self;
+
+        /// Create a new instance, returning an error instead of panicking on bad input lengths
+        ///
+        /// ## Arguments
+        /// - `key` - A slice of `KEY_SIZE` u8 values
+        /// - `nonce` - A slice of `NONCE_SIZE` u8 values
+        ///
+        /// ## Returns
+        /// `Error::InvalidKeyLength` or `Error::InvalidNonceLength` if a slice has the wrong length
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl, Error};
+        /// assert!(ChaCha20Impl::try_new(&[0u8; 32], &[0u8; 12]).is_ok());
+        /// assert_eq!(ChaCha20Impl::try_new(&[0u8; 16], &[0u8; 12]).err(), Some(Error::InvalidKeyLength));
+        /// ```
+        fn try_new(key: &[u8], nonce: &[u8]) -> Result<Self, Error> {
+            if key.len() != Self::KEY_SIZE {
+                return Err(Error::InvalidKeyLength);
+            }
+            if nonce.len() != Self::NONCE_SIZE {
+                return Err(Error::InvalidNonceLength);
+            }
+
+            Ok(Self::new(key, nonce))
+        }
+
         fn apply_keystream(&mut self, input: &mut [u8]);
         fn seek(&mut self, pos: u32);
+        fn seek_pos(&mut self, pos: u64) -> Result<(), Error>;
+        fn current_pos(&self) -> u64;
+        fn remaining_keystream(&self) -> u64;
+
+        /// Apply the keystream to the input, refusing to run past the end of the block counter
+        ///
+        /// Unlike `apply_keystream`, which lets the counter wrap around and repeat the keystream,
+        /// nothing is processed when `input` is longer than [`ChaCha20::remaining_keystream`].
+        ///
+        /// ## Arguments
+        /// - `input` - A mutable slice of u8 values to apply the keystream to
+        ///
+        /// ## Returns
+        /// `Error::KeystreamExhausted` if the input does not fit in the remaining keystream
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl, Error};
+        ///
+        /// let mut chacha = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+        /// chacha.seek(u32::MAX);
+        /// assert_eq!(chacha.try_apply_keystream(&mut [0u8; 65]), Err(Error::KeystreamExhausted));
+        /// assert_eq!(chacha.try_apply_keystream(&mut [0u8; 64]), Ok(()));
+        /// ```
+        fn try_apply_keystream(&mut self, input: &mut [u8]) -> Result<(), Error> {
+            if input.len() as u64 > self.remaining_keystream() {
+                return Err(Error::KeystreamExhausted);
+            }
+
+            self.apply_keystream(input);
+            Ok(())
+        }
     }
+
+    /// Errors returned by the ChaCha20 cipher
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The key does not have the length required by the cipher
+        InvalidKeyLength,
+        /// The nonce does not have the length required by the cipher
+        InvalidNonceLength,
+        /// The requested keystream position is past the end of the 32-bit block counter
+        PositionOutOfRange,
+        /// The input is longer than the keystream left before the block counter wraps around
+        KeystreamExhausted,
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::InvalidKeyLength => write!(f, "Invalid key length"),
+                Error::InvalidNonceLength => write!(f, "Invalid nonce length"),
+                Error::PositionOutOfRange => write!(f, "Position is out of the keystream range"),
+                Error::KeystreamExhausted => write!(f, "Keystream is exhausted"),
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl std::error::Error for Error {}
     
-    pub struct ChaCha20Impl {
+    /// ChaCha stream cipher with `ROUNDS` rounds, which must be a positive even number
+    #[derive(Clone)]
+    pub struct ChaChaImpl<const ROUNDS: usize> {
         state: [u32; 16],
+        keystream: [u8; 64],
+        offset: usize,
+        exhausted: bool,
+        backend: Backend,
     }
+
+    /// Number of keystream bytes addressable by the 32-bit block counter
+    const KEYSTREAM_LEN: u64 = 64 << 32;
+
+    /// ChaCha with 8 rounds, for fast non-cryptographic-strength randomness
+    pub type ChaCha8Impl = ChaChaImpl<8>;
+
+    /// ChaCha with 12 rounds
+    pub type ChaCha12Impl = ChaChaImpl<12>;
+
+    /// ChaCha with the full 20 rounds of RFC 8439
+    pub type ChaCha20Impl = ChaChaImpl<20>;
     
     const _SIGMA: &str = "expand 32-byte k";
-    const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+    pub(crate) const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
     
-    impl ChaCha20Impl {
-        fn quarter_round(&mut self, a: usize, b: usize, c: usize, d: usize, state: &mut [u32; 16]) {
+    impl<const ROUNDS: usize> ChaChaImpl<ROUNDS> {
+        /// Create a new instance from a fixed-size key and nonce, which cannot fail
+        ///
+        /// ## Arguments
+        /// - `key` - A 256-bit key
+        /// - `nonce` - A 96-bit nonce
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::chacha20::ChaCha20Impl;
+        /// let chacha = ChaCha20Impl::from_key_nonce(&[0u8; 32], &[0u8; 12]);
+        /// ```
+        pub fn from_key_nonce(key: &[u8; 32], nonce: &[u8; 12]) -> Self {
+            let mut new_key = [0u32; 8];
+            let mut new_nonce = [0u32; 3];
+            
+            let key_chunks = key.chunks_exact(4);
+            for (i, chunk) in key_chunks.enumerate() {
+                new_key[i] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            }
+    
+            let nonce_chunks = nonce.chunks_exact(4);
+            for (i, chunk) in nonce_chunks.enumerate() {
+                new_nonce[i] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            }
+    
+            let mut state = [0u32; 16];
+            state[0..4].copy_from_slice(&CONSTANTS);
+            state[4..12].copy_from_slice(&new_key);
+            state[12] = 0;
+            state[13..16].copy_from_slice(&new_nonce);
+            
+            let chacha = ChaChaImpl {
+                state,
+                keystream: [0u8; 64],
+                offset: 64,
+                exhausted: false,
+                backend: Backend::detect(),
+            };
+
+            wipe(&mut new_key);
+            wipe(&mut state);
+            chacha
+        }
+
+        /// Get the backend used to generate the keystream
+        pub fn backend(&self) -> Backend {
+            self.backend
+        }
+
+        /// Select the backend used to generate the keystream
+        ///
+        /// New instances use [`Backend::detect`]. All backends produce the same keystream.
+        ///
+        /// ## Panics
+        /// If the backend is not supported by the current CPU, see [`Backend::is_available`]
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::backends::Backend;
+        /// use chacha20imp::chacha20::ChaCha20Impl;
+        /// let mut chacha = ChaCha20Impl::from_key_nonce(&[0u8; 32], &[0u8; 12]);
+        /// chacha.set_backend(Backend::Soft);
+        /// ```
+        pub fn set_backend(&mut self, backend: Backend) {
+            assert!(backend.is_available(), "Backend {backend:?} is not supported by this CPU");
+            self.backend = backend;
+        }
+
+        /// Generate the block at the current counter and advance the counter
+        fn next_block(&mut self) {
+            Self::chacha20_keystream_block(&self.state, &mut self.keystream);
+            self.state[12] = self.state[12].wrapping_add(1);
+            // The counter only wraps to 0 after the last block of the stream
+            self.exhausted = self.state[12] == 0;
+        }
+
+        const DOUBLE_ROUNDS: usize = {
+            assert!(ROUNDS > 0 && ROUNDS.is_multiple_of(2), "ChaCha round count must be a positive even number");
+            ROUNDS / 2
+        };
+
+        fn quarter_round(a: usize, b: usize, c: usize, d: usize, state: &mut [u32; 16]) {
             state[a] = state[a].wrapping_add(state[b]);
             state[d] = (state[a] ^ state[d]).rotate_left(16);
     
@@ -50,35 +295,90 @@ pub mod chacha20 {
             state[b] = (state[b] ^ state[c]).rotate_left(7);
         }
     
-        fn chacha20_keystream_block(&mut self) -> [u8; 64] {
-            let mut state = self.state.clone();
-            
-            for _ in 0..10 {
+        /// Apply the ChaCha rounds to `state`, without the final feed-forward addition
+        pub(crate) fn rounds(state: &mut [u32; 16]) {
+            for _ in 0..Self::DOUBLE_ROUNDS {
                 // column rounds
-                self.quarter_round(0, 4, 8, 12, &mut state);
-                self.quarter_round(1, 5, 9, 13, &mut state);
-                self.quarter_round(2, 6, 10, 14, &mut state);
-                self.quarter_round(3, 7, 11, 15, &mut state);
+                Self::quarter_round(0, 4, 8, 12, state);
+                Self::quarter_round(1, 5, 9, 13, state);
+                Self::quarter_round(2, 6, 10, 14, state);
+                Self::quarter_round(3, 7, 11, 15, state);
     
                 // diagonal rounds
-                self.quarter_round(0, 5, 10, 15, &mut state);
-                self.quarter_round(1, 6, 11, 12, &mut state);
-                self.quarter_round(2, 7, 8, 13, &mut state);
-                self.quarter_round(3, 4, 9, 14, &mut state);
+                Self::quarter_round(0, 5, 10, 15, state);
+                Self::quarter_round(1, 6, 11, 12, state);
+                Self::quarter_round(2, 7, 8, 13, state);
+                Self::quarter_round(3, 4, 9, 14, state);
             }
+        }
     
-            for (s1, s0) in state.iter_mut().zip(self.state.iter()) {
-                *s1 = s1.wrapping_add(*s0);
+        /// Apply `N` bytes of keystream at a time from a multi-block backend, starting on a block
+        /// boundary, and return the tail that is shorter than `N`
+        fn apply_blocks<'a, const N: usize>(
+            &mut self,
+            mut input: &'a mut [u8],
+            mut generate: impl FnMut(&mut [u32; 16], &mut [u8; N]),
+        ) -> &'a mut [u8] {
+            if input.len() < N {
+                return input;
             }
+
+            let mut keystream = [0u8; N];
+            while input.len() >= N {
+                let (chunk, rest) = core::mem::take(&mut input).split_at_mut(N);
+                generate(&mut self.state, &mut keystream);
+                for (word, key) in chunk.chunks_exact_mut(4).zip(keystream.chunks_exact(4)) {
+                    let xored = u32::from_ne_bytes([word[0], word[1], word[2], word[3]])
+                        ^ u32::from_ne_bytes([key[0], key[1], key[2], key[3]]);
+                    word.copy_from_slice(&xored.to_ne_bytes());
+                }
+                self.exhausted = self.state[12] == 0;
+                input = rest;
+            }
+
+            wipe(&mut keystream);
+
+            input
+        }
+
+        /// Compute the keystream block for `input`, which holds the constants, key, counter and nonce,
+        /// and write it to `output` without allocating
+        pub(crate) fn chacha20_keystream_block(input: &[u32; 16], output: &mut [u8; 64]) {
+            let mut state = *input;
+            Self::rounds(&mut state);
     
-            let mut keystream = [0u8; 64];
-            keystream.copy_from_slice(&state[0..16].iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>());
-    
-            keystream
+            for ((chunk, s1), s0) in output.chunks_exact_mut(4).zip(state.iter()).zip(input.iter()) {
+                chunk.copy_from_slice(&s1.wrapping_add(*s0).to_le_bytes());
+            }
+
+            wipe(&mut state);
         }
     }
     
-    impl ChaCha20 for ChaCha20Impl {
+    #[cfg(feature = "zeroize")]
+    impl<const ROUNDS: usize> Zeroize for ChaChaImpl<ROUNDS> {
+        /// Wipe the key, nonce, counter and buffered keystream
+        fn zeroize(&mut self) {
+            self.state.zeroize();
+            self.keystream.zeroize();
+            self.offset.zeroize();
+            self.exhausted.zeroize();
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    impl<const ROUNDS: usize> Drop for ChaChaImpl<ROUNDS> {
+        fn drop(&mut self) {
+            self.zeroize();
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    impl<const ROUNDS: usize> ZeroizeOnDrop for ChaChaImpl<ROUNDS> {}
+
+    impl<const ROUNDS: usize> ChaCha20 for ChaChaImpl<ROUNDS> {
+        const NONCE_SIZE: usize = 12;
+
         /// Create a new ChaCha20 instance
         /// 
         /// ## Arguments
@@ -87,6 +387,10 @@ pub mod chacha20 {
         ///
         /// ## Returns
         /// A new ChaCha20 instance
+        ///
+        /// ## Panics
+        /// If the key or nonce has the wrong length. Use [`ChaCha20::try_new`] or
+        /// [`ChaChaImpl::from_key_nonce`] to avoid the panic
         /// 
         /// ## Example
         /// ```
@@ -94,39 +398,21 @@ pub mod chacha20 {
         /// let chacha = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
         /// ```
         fn new(key: &[u8], nonce: &[u8]) -> Self {
-            if key.len() != 32 {
-                panic!("Key must be 256 bits (32 u8 values)");
-            }
-            if nonce.len() != 12 {
-                panic!("Nonce must be 96 bits (12 u8 values)");
-            }
-            
-            let mut new_key = [0u32; 8];
-            let mut new_nonce = [0u32; 3];
-            
-            let key_chunks = key.chunks_exact(4);
-            for (i, chunk) in key_chunks.enumerate() {
-                new_key[i] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-            }
-    
-            let nonce_chunks = nonce.chunks_exact(4);
-            for (i, chunk) in nonce_chunks.enumerate() {
-                new_nonce[i] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-            }
-    
-            let mut state = [0u32; 16];
-            state[0..4].copy_from_slice(&CONSTANTS);
-            state[4..12].copy_from_slice(&new_key);
-            state[12] = 0;
-            state[13..16].copy_from_slice(&new_nonce);
-            
-            ChaCha20Impl {
-                state,
+            match (key.try_into(), nonce.try_into()) {
+                (Ok(key), Ok(nonce)) => Self::from_key_nonce(key, nonce),
+                (Err(_), _) => panic!("Key must be 256 bits (32 u8 values)"),
+                (_, Err(_)) => panic!("Nonce must be 96 bits (12 u8 values)"),
             }
         }
 
         /// Apply the keystream to the input
         ///
+        /// The unused part of the current keystream block is kept between calls, so splitting
+        /// the input into chunks of any size gives the same output as a single call.
+        ///
+        /// The 32-bit block counter wraps around after 256 GiB, which repeats the keystream. Use
+        /// [`ChaCha20::try_apply_keystream`] to get an error instead.
+        ///
         /// ## Arguments
         /// - `input` - A mutable slice of u8 values to apply the keystream to
         ///
@@ -139,13 +425,38 @@ pub mod chacha20 {
         /// chacha.apply_keystream(&mut input);
         /// ```
         fn apply_keystream(&mut self, input: &mut [u8]) {
-            let mut keystream: [u8; 64] = [0; 64];
-            for i in 0..input.len() {
-                if i % 64 == 0 {
-                    keystream = self.chacha20_keystream_block();
-                    self.state[12] = self.state[12].wrapping_add(1);
+            // Use up the buffered block first so the backend starts on a block boundary
+            let buffered = input.len().min(64 - self.offset);
+            let (head, input) = input.split_at_mut(buffered);
+            for (byte, key) in head.iter_mut().zip(self.keystream[self.offset..].iter()) {
+                *byte ^= key;
+            }
+            self.offset += buffered;
+
+            // SAFETY: `set_backend` only accepts backends supported by the CPU
+            let input = match self.backend {
+                Backend::Soft => self.apply_blocks(input, |state, output| {
+                    soft::keystream_blocks(state, Self::DOUBLE_ROUNDS, output)
+                }),
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                Backend::Sse2 => self.apply_blocks(input, |state, output| unsafe {
+                    sse2::keystream_blocks(state, Self::DOUBLE_ROUNDS, output)
+                }),
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                Backend::Avx2 => self.apply_blocks(input, |state, output| unsafe {
+                    avx2::keystream_blocks(state, Self::DOUBLE_ROUNDS, output)
+                }),
+                #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+                Backend::Sse2 | Backend::Avx2 => input,
+            };
+
+            for byte in input.iter_mut() {
+                if self.offset == 64 {
+                    self.next_block();
+                    self.offset = 0;
                 }
-                input[i] ^= keystream[i % 64 as usize];
+                *byte ^= self.keystream[self.offset];
+                self.offset += 1;
             }
         }
 
@@ -162,6 +473,118 @@ pub mod chacha20 {
         /// ```
         fn seek(&mut self, pos: u32) {
             self.state[12] = pos;
+            self.offset = 64;
+            self.exhausted = false;
+        }
+
+        /// Seek to a byte position in the keystream
+        ///
+        /// The position is split into a block counter and an offset inside that block, so
+        /// decryption can resume at any byte of a stream.
+        ///
+        /// ## Arguments
+        /// - `pos` - A u64 byte offset from the start of the keystream
+        ///
+        /// ## Returns
+        /// `Error::PositionOutOfRange` if `pos` is past the end of the keystream. The end itself,
+        /// where [`ChaCha20::current_pos`] leaves an exhausted keystream, is a valid position
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        /// let mut chacha = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+        /// chacha.seek_pos(1000).unwrap();
+        /// assert_eq!(chacha.current_pos(), 1000);
+        /// ```
+        fn seek_pos(&mut self, pos: u64) -> Result<(), Error> {
+            if pos == KEYSTREAM_LEN {
+                self.seek(0);
+                self.exhausted = true;
+                return Ok(());
+            }
+
+            let block = pos / 64;
+            if block > u32::MAX as u64 {
+                return Err(Error::PositionOutOfRange);
+            }
+
+            self.seek(block as u32);
+
+            let offset = (pos % 64) as usize;
+            if offset != 0 {
+                self.next_block();
+                self.offset = offset;
+            }
+
+            Ok(())
+        }
+
+        /// Get the current byte position in the keystream
+        ///
+        /// ## Returns
+        /// The u64 byte offset of the next keystream byte to be applied
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        /// let mut chacha = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+        /// chacha.apply_keystream(&mut [0u8; 70]);
+        /// assert_eq!(chacha.current_pos(), 70);
+        /// ```
+        fn current_pos(&self) -> u64 {
+            if self.offset == 64 {
+                if self.exhausted {
+                    KEYSTREAM_LEN
+                } else {
+                    self.state[12] as u64 * 64
+                }
+            } else {
+                self.state[12].wrapping_sub(1) as u64 * 64 + self.offset as u64
+            }
+        }
+
+        /// Get the number of keystream bytes left before the block counter wraps around
+        ///
+        /// Callers can use this to rotate the nonce before the keystream would repeat.
+        ///
+        /// ## Returns
+        /// The number of bytes that can still be processed with this key and nonce
+        ///
+        /// ## Example
+        /// ```
+        /// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        /// let mut chacha = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+        /// chacha.seek(u32::MAX);
+        /// assert_eq!(chacha.remaining_keystream(), 64);
+        /// ```
+        fn remaining_keystream(&self) -> u64 {
+            KEYSTREAM_LEN - self.current_pos()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub mod async_io;
+pub mod backends;
+pub mod chacha20legacy;
+pub mod chacha20poly1305;
+#[cfg(feature = "cipher")]
+mod cipher_traits;
+#[cfg(feature = "cipher")]
+pub use cipher;
+#[cfg(feature = "alloc")]
+pub mod container;
+pub mod fast_key_erasure;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "kdf")]
+pub mod kdf;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod poly1305;
+#[cfg(feature = "rand_core")]
+pub use rand_core;
+#[cfg(feature = "rand_core")]
+pub mod rng;
+pub mod stream;
+pub mod xchacha20;
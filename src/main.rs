@@ -0,0 +1,498 @@
+//! # chacha20imp command-line tool
+//!
+//! Encrypts and decrypts files and pipes in the [`chacha20imp::container`] format, with
+//! XChaCha20-Poly1305 segments by default.
+//!
+//! ```text
+//! chacha20imp keygen -o secret.key
+//! chacha20imp encrypt --key-file secret.key -i notes.txt -o notes.enc
+//! chacha20imp decrypt --key-file secret.key < notes.enc > notes.txt
+//! CHACHA20IMP_KEY=<64 hex digits> chacha20imp decrypt --key-env CHACHA20IMP_KEY -i notes.enc
+//! chacha20imp encrypt --password -i notes.txt -o notes.enc
+//! ```
+//!
+//! Keys are 32 bytes, written as 64 hex digits. A key file may also hold the 32 raw bytes.
+//! Input and output default to standard input and output, which `-` also selects. The output
+//! must not be the input file, and a new output file is only readable by its owner.
+//!
+//! With `--password` or `--password-env`, the key is derived from a password with Argon2id, or
+//! scrypt with `--kdf scrypt`, and the KDF parameters are stored in the container header.
+//! `--kdf-params` changes the cost, for example `m=65536,t=3,p=4` for Argon2id or `ln=18,r=8,p=1`
+//! for scrypt.
+//!
+//! When decryption fails, a partially written output file is removed. Segments written to
+//! standard output before the failure cannot be taken back, so always check the exit code:
+//! - 0 - success
+//! - 1 - reading or writing failed
+//! - 2 - invalid command line
+//! - 3 - the key or password could not be read
+//! - 4 - wrong key
+//! - 5 - the input is not a container, or was truncated or modified
+//!
+//! Built with the `cli` feature: `cargo install --path . --features cli`.
+
+use chacha20imp::container::{
+    self, read_header, Algorithm, ContainerReader, ContainerWriter, Header, MAX_SEGMENT_SIZE, SALT_SIZE,
+};
+use chacha20imp::kdf::{self, Argon2Params, Kdf, Limits, ScryptParams};
+use chacha20imp::stream::{self, DEFAULT_SEGMENT_SIZE, X_NONCE_PREFIX_SIZE};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(name = "chacha20imp", version, about = "Encrypt and decrypt files with ChaCha20-Poly1305")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt the input into a container
+    Encrypt {
+        #[command(flatten)]
+        key: KeySource,
+        #[command(flatten)]
+        files: Files,
+        /// AEAD for the segments
+        #[arg(long, value_enum, default_value_t = AlgorithmArg::XChaCha20Poly1305)]
+        algorithm: AlgorithmArg,
+        /// KDF for a password
+        #[arg(long, value_enum, default_value_t = KdfArg::Argon2id)]
+        kdf: KdfArg,
+        /// KDF cost, overriding the defaults: m (KiB), t and p for Argon2id, ln, r and p for scrypt
+        #[arg(long, value_name = "NAME=VALUE,...")]
+        kdf_params: Option<String>,
+        /// Plaintext bytes per authenticated segment
+        #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_SEGMENT_SIZE as u32,
+              value_parser = clap::value_parser!(u32).range(1..=MAX_SEGMENT_SIZE as i64))]
+        segment_size: u32,
+    },
+    /// Check the key and decrypt a container
+    Decrypt {
+        #[command(flatten)]
+        key: KeySource,
+        #[command(flatten)]
+        files: Files,
+    },
+    /// Generate a random key as 64 hex digits
+    Keygen {
+        /// Key file to create, standard output if omitted or "-"; an existing file is never
+        /// overwritten
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct KeySource {
+    /// Read the key from a file holding 64 hex digits or 32 raw bytes
+    #[arg(long, value_name = "PATH")]
+    key_file: Option<PathBuf>,
+    /// Read the key as 64 hex digits from an environment variable
+    #[arg(long, value_name = "VAR")]
+    key_env: Option<String>,
+    /// Prompt for a password and derive the key from it
+    #[arg(long)]
+    password: bool,
+    /// Read the password from an environment variable and derive the key from it
+    #[arg(long, value_name = "VAR")]
+    password_env: Option<String>,
+}
+
+#[derive(Args)]
+struct Files {
+    /// Input file, standard input if omitted or "-"
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+    /// Output file, standard output if omitted or "-"
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+impl Files {
+    /// Refuse to write over the input, which would be truncated before it is read
+    fn check_distinct(&self) {
+        if let (Some(input), Some(output)) = (stdio_or(&self.input), stdio_or(&self.output)) {
+            if same_file(input, output) {
+                Cli::command()
+                    .error(ErrorKind::ArgumentConflict, "the input and output are the same file")
+                    .exit();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AlgorithmArg {
+    #[value(name = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+    #[value(name = "xchacha20-poly1305")]
+    XChaCha20Poly1305,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum KdfArg {
+    Argon2id,
+    Scrypt,
+}
+
+impl KdfArg {
+    /// Apply comma-separated `name=value` settings to the default parameters
+    ///
+    /// Costs above the default [`Limits`] are refused, as decryption would reject them.
+    fn with_params(self, settings: Option<&str>) -> Result<Kdf, String> {
+        let settings = settings.unwrap_or("").split(',').filter(|setting| !setting.is_empty());
+        let mut values = Vec::new();
+        for setting in settings {
+            let (name, value) = setting
+                .split_once('=')
+                .and_then(|(name, value)| Some((name, value.parse::<u32>().ok()?)))
+                .ok_or_else(|| format!("invalid KDF setting '{setting}'"))?;
+            values.push((name, value));
+        }
+
+        let unknown = |name: &str| format!("unknown KDF setting '{name}'");
+        let kdf = match self {
+            KdfArg::Argon2id => {
+                let default = Argon2Params::default();
+                let (mut m, mut t, mut p) = (default.memory_kib(), default.iterations(), default.parallelism());
+                for (name, value) in values {
+                    match name {
+                        "m" => m = value,
+                        "t" => t = value,
+                        "p" => p = value,
+                        _ => return Err(unknown(name)),
+                    }
+                }
+                Argon2Params::new(m, t, p).map(Kdf::Argon2id)
+            }
+            KdfArg::Scrypt => {
+                let default = ScryptParams::default();
+                let (mut ln, mut r, mut p) = (default.log_n() as u32, default.r(), default.p());
+                for (name, value) in values {
+                    match name {
+                        "ln" => ln = value,
+                        "r" => r = value,
+                        "p" => p = value,
+                        _ => return Err(unknown(name)),
+                    }
+                }
+                u8::try_from(ln)
+                    .map_err(|_| kdf::Error::InvalidParams)
+                    .and_then(|ln| ScryptParams::new(ln, r, p))
+                    .map(Kdf::Scrypt)
+            }
+        }
+        .map_err(|error| error.to_string())?;
+
+        Limits::default()
+            .check(&kdf)
+            .map_err(|_| "the KDF cost is above the limits that decryption accepts".to_string())?;
+        Ok(kdf)
+    }
+}
+
+impl From<AlgorithmArg> for Algorithm {
+    fn from(algorithm: AlgorithmArg) -> Self {
+        match algorithm {
+            AlgorithmArg::ChaCha20Poly1305 => Algorithm::ChaCha20Poly1305,
+            AlgorithmArg::XChaCha20Poly1305 => Algorithm::XChaCha20Poly1305,
+        }
+    }
+}
+
+/// Reasons for a non-zero exit, each with its own exit code
+enum Failure {
+    Io(io::Error),
+    Key(String),
+    WrongKey,
+    InvalidInput(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Io(_) => 1,
+            Failure::Key(_) => 3,
+            Failure::WrongKey => 4,
+            Failure::InvalidInput(_) => 5,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Io(error) => write!(f, "{error}"),
+            Failure::Key(message) => write!(f, "{message}"),
+            Failure::WrongKey => write!(f, "{}", container::Error::WrongKey),
+            Failure::InvalidInput(message) => write!(f, "invalid input: {message}"),
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    /// Tell container and segment errors, which the adapters wrap as `InvalidData`, from I/O errors
+    fn from(error: io::Error) -> Self {
+        let inner = error.get_ref();
+        if let Some(error) = inner.and_then(|inner| inner.downcast_ref::<container::Error>()) {
+            return match error {
+                container::Error::WrongKey => Failure::WrongKey,
+                error => Failure::InvalidInput(error.to_string()),
+            };
+        }
+        if let Some(error) = inner.and_then(|inner| inner.downcast_ref::<stream::Error>()) {
+            return Failure::InvalidInput(error.to_string());
+        }
+
+        Failure::Io(error)
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Encrypt {
+            key,
+            files,
+            algorithm,
+            kdf,
+            kdf_params,
+            segment_size,
+        } => {
+            files.check_distinct();
+            let kdf = kdf
+                .with_params(kdf_params.as_deref())
+                .unwrap_or_else(|message| Cli::command().error(ErrorKind::ValueValidation, message).exit());
+            encrypt(&key, &files, algorithm.into(), kdf, segment_size)
+        }
+        Command::Decrypt { key, files } => {
+            files.check_distinct();
+            decrypt(&key, &files)
+        }
+        Command::Keygen { output } => keygen(stdio_or(&output)),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("chacha20imp: {failure}");
+            ExitCode::from(failure.exit_code())
+        }
+    }
+}
+
+fn encrypt(source: &KeySource, files: &Files, algorithm: Algorithm, kdf: Kdf, segment_size: u32) -> Result<(), Failure> {
+    let secret = load_secret(source, true)?;
+    let mut salt = [0u8; SALT_SIZE];
+    let mut nonce = [0u8; X_NONCE_PREFIX_SIZE];
+    random(&mut salt)?;
+    random(&mut nonce)?;
+    let mut header = Header::new(algorithm, salt, nonce).with_segment_size(segment_size);
+
+    let key = match secret {
+        Secret::Key(key) => key,
+        Secret::Password(password) => {
+            header = header.with_kdf(kdf.into());
+            password_key(&password, &salt, &kdf)?
+        }
+    };
+
+    let mut input = open_input(stdio_or(&files.input))?;
+    write_output(stdio_or(&files.output), |output| {
+        let mut writer = ContainerWriter::new(output, &key, &header)?;
+        io::copy(&mut input, &mut writer)?;
+        writer.finish()?;
+        Ok(())
+    })
+}
+
+fn decrypt(source: &KeySource, files: &Files) -> Result<(), Failure> {
+    let secret = load_secret(source, false)?;
+    let mut input = open_input(stdio_or(&files.input))?;
+    let header = read_header(&mut input)?;
+
+    let key = match secret {
+        Secret::Key(key) => key,
+        Secret::Password(password) => {
+            let params = header
+                .kdf()
+                .ok_or_else(|| Failure::Key("the container was encrypted with a key, not a password".into()))?;
+            let kdf = Kdf::try_from(params).map_err(|error| Failure::InvalidInput(error.to_string()))?;
+            password_key(&password, header.salt(), &kdf)?
+        }
+    };
+
+    // The key is checked against the header before the output is created
+    let mut reader = ContainerReader::with_header(input, &key, header)?;
+    write_output(stdio_or(&files.output), |output| {
+        io::copy(&mut reader, output)?;
+        output.flush()
+    })
+}
+
+fn keygen(output: Option<&Path>) -> Result<(), Failure> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    random(&mut key[..])?;
+    let mut line = Zeroizing::new([b'\n'; 65]);
+    for (byte, pair) in key.iter().zip(line.chunks_exact_mut(2)) {
+        pair[0] = HEX_DIGITS[(byte >> 4) as usize];
+        pair[1] = HEX_DIGITS[(byte & 0x0f) as usize];
+    }
+
+    match output {
+        Some(path) => create_key_file(path)?.write_all(&line[..])?,
+        None => io::stdout().write_all(&line[..])?,
+    }
+    Ok(())
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Treat a missing path and "-" alike as standard input or output
+fn stdio_or(path: &Option<PathBuf>) -> Option<&Path> {
+    path.as_deref().filter(|path| *path != Path::new("-"))
+}
+
+fn open_input(path: Option<&Path>) -> Result<Box<dyn Read>, Failure> {
+    match path {
+        Some(path) => Ok(Box::new(File::open(path).map_err(|error| in_file(path, error))?)),
+        None => Ok(Box::new(io::stdin().lock())),
+    }
+}
+
+/// Whether both paths name the same existing file, also through links
+fn same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Run `write` on the output, removing a partially written output file when it fails
+///
+/// A new output file is only readable by its owner; an existing one keeps its permissions.
+fn write_output(path: Option<&Path>, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> Result<(), Failure> {
+    let Some(path) = path else {
+        return Ok(write(&mut io::stdout().lock())?);
+    };
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path).map_err(|error| in_file(path, error))?;
+    write(&mut file).inspect_err(|_| {
+        let _ = fs::remove_file(path);
+    })?;
+    Ok(())
+}
+
+fn create_key_file(path: &Path) -> Result<File, Failure> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path).map_err(|error| in_file(path, error))
+}
+
+/// Add the path to errors from opening a file
+fn in_file(path: &Path, error: io::Error) -> Failure {
+    Failure::Io(io::Error::new(error.kind(), format!("{}: {error}", path.display())))
+}
+
+/// A raw key, or a password to derive the key from
+enum Secret {
+    Key(Zeroizing<[u8; 32]>),
+    Password(Zeroizing<String>),
+}
+
+/// Load the key or password, asking for a password twice when `confirm` is set
+fn load_secret(source: &KeySource, confirm: bool) -> Result<Secret, Failure> {
+    if let Some(path) = &source.key_file {
+        let contents = Zeroizing::new(
+            fs::read(path).map_err(|error| Failure::Key(format!("cannot read key file {}: {error}", path.display())))?,
+        );
+        if let Ok(key) = <[u8; 32]>::try_from(&contents[..]) {
+            return Ok(Secret::Key(Zeroizing::new(key)));
+        }
+        return parse_hex_key(&contents)
+            .map(Secret::Key)
+            .ok_or_else(|| Failure::Key(format!("key file {} does not hold a 32-byte key", path.display())));
+    }
+
+    if let Some(var) = &source.key_env {
+        let value = env_var(var)?;
+        return parse_hex_key(value.as_bytes())
+            .map(Secret::Key)
+            .ok_or_else(|| Failure::Key(format!("environment variable {var} does not hold 64 hex digits")));
+    }
+
+    let password = match &source.password_env {
+        Some(var) => env_var(var)?,
+        None => {
+            let password = prompt_password("Password: ")?;
+            if confirm && *prompt_password("Confirm password: ")? != *password {
+                return Err(Failure::Key("passwords do not match".into()));
+            }
+            password
+        }
+    };
+    if password.is_empty() {
+        return Err(Failure::Key("the password is empty".into()));
+    }
+    Ok(Secret::Password(password))
+}
+
+fn env_var(var: &str) -> Result<Zeroizing<String>, Failure> {
+    std::env::var(var)
+        .map(Zeroizing::new)
+        .map_err(|_| Failure::Key(format!("environment variable {var} is not set")))
+}
+
+/// Read a password from the terminal without echoing it
+fn prompt_password(prompt: &str) -> Result<Zeroizing<String>, Failure> {
+    rpassword::prompt_password(prompt)
+        .map(Zeroizing::new)
+        .map_err(|error| Failure::Key(format!("cannot read the password: {error}")))
+}
+
+fn password_key(password: &str, salt: &[u8], kdf: &Kdf) -> Result<Zeroizing<[u8; 32]>, Failure> {
+    let key = kdf::derive_key(password.as_bytes(), salt, kdf).map_err(|error| Failure::InvalidInput(error.to_string()))?;
+    Ok(Zeroizing::new(*key.as_bytes()))
+}
+
+/// Parse 64 hex digits, ignoring surrounding whitespace such as a trailing newline
+fn parse_hex_key(text: &[u8]) -> Option<Zeroizing<[u8; 32]>> {
+    let text = text.trim_ascii();
+    if text.len() != 64 {
+        return None;
+    }
+
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let mut key = Zeroizing::new([0u8; 32]);
+    for (byte, pair) in key.iter_mut().zip(text.chunks_exact(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(key)
+}
+
+fn random(dest: &mut [u8]) -> Result<(), Failure> {
+    getrandom::getrandom(dest).map_err(|error| Failure::Io(error.into()))
+}
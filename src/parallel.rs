@@ -0,0 +1,107 @@
+//! # Parallel keystream application
+//!
+//! Every ChaCha20 block can be computed from its counter alone, so a large buffer can be split on
+//! block boundaries and encrypted by several threads, each with its own copy of the cipher seeked
+//! to the start of its chunk. The output is identical to [`ChaCha20::apply_keystream`].
+//!
+//! Available with the `rayon` feature.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+//! use chacha20imp::parallel::ParallelKeystream;
+//!
+//! let mut cipher = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+//! let mut buf = vec![0u8; 1 << 20];
+//! cipher.par_apply_keystream(&mut buf).unwrap();
+//! assert_eq!(cipher.current_pos(), 1 << 20);
+//! ```
+
+use crate::chacha20::{ChaCha20, Error};
+use rayon::prelude::*;
+
+/// Default smallest amount of data, in bytes, handed to a single worker
+pub const DEFAULT_MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Apply the keystream to large buffers on the rayon thread pool
+///
+/// Implemented for every cipher that can be cloned and shared between threads.
+pub trait ParallelKeystream: ChaCha20 + Clone + Send + Sync {
+    /// Apply the keystream to the input in parallel, in chunks of at least
+    /// [`DEFAULT_MIN_CHUNK_SIZE`] bytes
+    ///
+    /// ## Arguments
+    /// - `input` - A mutable slice of u8 values to apply the keystream to
+    ///
+    /// ## Returns
+    /// `Error::KeystreamExhausted` if the input does not fit in the remaining keystream
+    fn par_apply_keystream(&mut self, input: &mut [u8]) -> Result<(), Error> {
+        self.par_apply_keystream_with_min_chunk(input, DEFAULT_MIN_CHUNK_SIZE)
+    }
+
+    /// Apply the keystream to the input in parallel, in chunks of at least `min_chunk_size` bytes
+    ///
+    /// The chunk size is rounded up to a whole number of 64-byte blocks. Buffers that fit in a
+    /// single chunk are processed on the current thread. Afterwards the cipher is at the same
+    /// position as after [`ChaCha20::apply_keystream`].
+    ///
+    /// Unlike `apply_keystream` the block counter never wraps around: nothing is processed when
+    /// `input` is longer than [`ChaCha20::remaining_keystream`].
+    ///
+    /// ## Arguments
+    /// - `input` - A mutable slice of u8 values to apply the keystream to
+    /// - `min_chunk_size` - The smallest number of bytes given to a worker
+    ///
+    /// ## Returns
+    /// `Error::KeystreamExhausted` if the input does not fit in the remaining keystream
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+    /// use chacha20imp::parallel::ParallelKeystream;
+    ///
+    /// let mut cipher = ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]);
+    /// let mut buf = vec![0u8; 100_000];
+    /// cipher.par_apply_keystream_with_min_chunk(&mut buf, 4096).unwrap();
+    /// ```
+    fn par_apply_keystream_with_min_chunk(&mut self, input: &mut [u8], min_chunk_size: usize) -> Result<(), Error> {
+        let pos = self.current_pos();
+        if input.len() as u64 > self.remaining_keystream() || pos.checked_add(input.len() as u64).is_none() {
+            return Err(Error::KeystreamExhausted);
+        }
+
+        // Finish the current block so that every chunk starts on a block boundary
+        let head_len = ((64 - pos % 64) % 64).min(input.len() as u64) as usize;
+        let (head, body) = input.split_at_mut(head_len);
+        self.apply_keystream(head);
+
+        let chunk_size = min_chunk_size.max(1).div_ceil(64) * 64;
+        if body.len() <= chunk_size {
+            self.apply_keystream(body);
+            return Ok(());
+        }
+
+        // The workers take every chunk but the last one, which leaves this cipher in the state
+        // the sequential path would have reached
+        let start = pos + head_len as u64;
+        let last = (body.len() - 1) / chunk_size * chunk_size;
+        let (body, tail) = body.split_at_mut(last);
+
+        let cipher = &*self;
+        body.par_chunks_mut(chunk_size)
+            .enumerate()
+            .try_for_each(|(i, chunk)| {
+                let mut worker = cipher.clone();
+                worker.seek_pos(start + (i * chunk_size) as u64)?;
+                worker.apply_keystream(chunk);
+                Ok(())
+            })?;
+
+        self.seek_pos(start + last as u64)?;
+        self.apply_keystream(tail);
+
+        Ok(())
+    }
+}
+
+impl<T: ChaCha20 + Clone + Send + Sync> ParallelKeystream for T {}
@@ -0,0 +1,323 @@
+//! # Poly1305 Message Authenticator
+//!
+//! The Poly1305 one-time authenticator as specified in RFC 8439, with an incremental
+//! `update`/`finalize` interface. A key must only ever authenticate a single message; the usual
+//! way to get one is [`poly1305_key_gen`], which takes it from block 0 of a ChaCha20 keystream.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::poly1305::{poly1305_key_gen, Poly1305};
+//!
+//! let key = poly1305_key_gen(&[0u8; 32], &[0u8; 12]);
+//! let mut mac = Poly1305::new(&key);
+//! mac.update(b"Hello, ");
+//! mac.update(b"Poly1305!");
+//! let tag = mac.finalize();
+//! println!("Tag: {:?}", tag);
+//! ```
+
+use crate::chacha20::{ChaCha20, ChaCha20Impl};
+use core::fmt;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Errors returned by the Poly1305 authenticator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The computed tag does not match the expected one
+    InvalidTag,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidTag => write!(f, "Authentication tag does not match"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Generate a Poly1305 one-time key from the first 32 bytes of ChaCha20 block 0
+///
+/// ## Arguments
+/// - `key` - A 256-bit ChaCha20 key
+/// - `nonce` - A 96-bit nonce, which must be unique for every message
+///
+/// ## Returns
+/// The 32-byte Poly1305 key
+///
+/// ## Example
+/// ```
+/// use chacha20imp::poly1305::poly1305_key_gen;
+/// let mac_key = poly1305_key_gen(&[0u8; 32], &[0u8; 12]);
+/// ```
+pub fn poly1305_key_gen(key: &[u8; 32], nonce: &[u8; 12]) -> [u8; 32] {
+    let mut cipher = ChaCha20Impl::from_key_nonce(key, nonce);
+
+    let mut mac_key = [0u8; 32];
+    cipher.apply_keystream(&mut mac_key);
+
+    mac_key
+}
+
+/// Poly1305 one-time authenticator using 26-bit limbs
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buffer: [u8; 16],
+    leftover: usize,
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Poly1305 {
+    /// Wipe the key, accumulator and buffered message bytes
+    fn zeroize(&mut self) {
+        self.r.zeroize();
+        self.h.zeroize();
+        self.pad.zeroize();
+        self.buffer.zeroize();
+        self.leftover.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Poly1305 {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for Poly1305 {}
+
+impl Poly1305 {
+    /// Create a new Poly1305 instance
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit one-time key: `r` (clamped internally) followed by `s`. It must never be used
+    ///   for more than one message
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::poly1305::Poly1305;
+    /// let mac = Poly1305::new(&[0u8; 32]);
+    /// ```
+    pub fn new(key: &[u8; 32]) -> Self {
+        let word = |i: usize| u32::from_le_bytes([key[i], key[i + 1], key[i + 2], key[i + 3]]);
+
+        // r is clamped as required by the specification
+        let r = [
+            word(0) & 0x03ff_ffff,
+            (word(3) >> 2) & 0x03ff_ff03,
+            (word(6) >> 4) & 0x03ff_c0ff,
+            (word(9) >> 6) & 0x03f0_3fff,
+            (word(12) >> 8) & 0x000f_ffff,
+        ];
+        let pad = [word(16), word(20), word(24), word(28)];
+
+        Poly1305 {
+            r,
+            h: [0u32; 5],
+            pad,
+            buffer: [0u8; 16],
+            leftover: 0,
+        }
+    }
+
+    /// Feed more of the message to the authenticator
+    ///
+    /// Data may be passed in pieces of any size; the result only depends on the concatenation.
+    ///
+    /// ## Arguments
+    /// - `data` - The next part of the message
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.leftover > 0 {
+            let take = (16 - self.leftover).min(data.len());
+            self.buffer[self.leftover..self.leftover + take].copy_from_slice(&data[..take]);
+            self.leftover += take;
+            data = &data[take..];
+
+            if self.leftover < 16 {
+                return;
+            }
+
+            let block = self.buffer;
+            self.block(&block, 1 << 24);
+            self.leftover = 0;
+        }
+
+        let mut chunks = data.chunks_exact(16);
+        for chunk in &mut chunks {
+            let mut block = [0u8; 16];
+            block.copy_from_slice(chunk);
+            self.block(&block, 1 << 24);
+        }
+
+        let rest = chunks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.leftover = rest.len();
+    }
+
+    /// Feed `data` followed by zero bytes up to the next multiple of 16
+    pub(crate) fn update_padded(&mut self, data: &[u8]) {
+        self.update(data);
+        if self.leftover > 0 {
+            let zeros = [0u8; 16];
+            let fill = 16 - self.leftover;
+            self.update(&zeros[..fill]);
+        }
+    }
+
+    fn block(&mut self, block: &[u8; 16], hibit: u32) {
+        const MASK: u32 = 0x03ff_ffff;
+        let word = |i: usize| u32::from_le_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]]);
+
+        let [r0, r1, r2, r3, r4] = self.r.map(|x| x as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h0 = self.h[0].wrapping_add(word(0) & MASK) as u64;
+        let h1 = self.h[1].wrapping_add((word(3) >> 2) & MASK) as u64;
+        let h2 = self.h[2].wrapping_add((word(6) >> 4) & MASK) as u64;
+        let h3 = self.h[3].wrapping_add((word(9) >> 6) & MASK) as u64;
+        let h4 = self.h[4].wrapping_add((word(12) >> 8) | hibit) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        // partial reduction modulo 2^130 - 5
+        let mut c = d0 >> 26;
+        let mut h0 = (d0 as u32) & MASK;
+        d1 += c;
+        c = d1 >> 26;
+        let h1 = (d1 as u32) & MASK;
+        d2 += c;
+        c = d2 >> 26;
+        let h2 = (d2 as u32) & MASK;
+        d3 += c;
+        c = d3 >> 26;
+        let h3 = (d3 as u32) & MASK;
+        d4 += c;
+        c = d4 >> 26;
+        let h4 = (d4 as u32) & MASK;
+        h0 += (c as u32) * 5;
+        let c = h0 >> 26;
+        h0 &= MASK;
+
+        self.h = [h0, h1 + c, h2, h3, h4];
+    }
+
+    /// Consume the authenticator and compute the tag
+    ///
+    /// ## Returns
+    /// The 16-byte tag of the whole message
+    pub fn finalize(mut self) -> [u8; 16] {
+        const MASK: u32 = 0x03ff_ffff;
+
+        if self.leftover > 0 {
+            let mut block = [0u8; 16];
+            block[..self.leftover].copy_from_slice(&self.buffer[..self.leftover]);
+            block[self.leftover] = 1;
+            self.block(&block, 0);
+        }
+
+        // full carry of h
+        let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+        let mut c = h1 >> 26;
+        h1 &= MASK;
+        h2 += c;
+        c = h2 >> 26;
+        h2 &= MASK;
+        h3 += c;
+        c = h3 >> 26;
+        h3 &= MASK;
+        h4 += c;
+        c = h4 >> 26;
+        h4 &= MASK;
+        h0 += c * 5;
+        c = h0 >> 26;
+        h0 &= MASK;
+        h1 += c;
+
+        // compute h - p and select it when h >= p
+        let mut g0 = h0.wrapping_add(5);
+        c = g0 >> 26;
+        g0 &= MASK;
+        let mut g1 = h1.wrapping_add(c);
+        c = g1 >> 26;
+        g1 &= MASK;
+        let mut g2 = h2.wrapping_add(c);
+        c = g2 >> 26;
+        g2 &= MASK;
+        let mut g3 = h3.wrapping_add(c);
+        c = g3 >> 26;
+        g3 &= MASK;
+        let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+
+        let select = (g4 >> 31).wrapping_sub(1);
+        h0 = (h0 & !select) | (g0 & select);
+        h1 = (h1 & !select) | (g1 & select);
+        h2 = (h2 & !select) | (g2 & select);
+        h3 = (h3 & !select) | (g3 & select);
+        h4 = (h4 & !select) | (g4 & select);
+
+        // h = (h + pad) mod 2^128
+        let words = [
+            h0 | (h1 << 26),
+            (h1 >> 6) | (h2 << 20),
+            (h2 >> 12) | (h3 << 14),
+            (h3 >> 18) | (h4 << 8),
+        ];
+
+        let mut tag = [0u8; 16];
+        let mut carry = 0u64;
+        for (i, (w, p)) in words.iter().zip(self.pad.iter()).enumerate() {
+            let sum = *w as u64 + *p as u64 + carry;
+            tag[i * 4..i * 4 + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+
+        tag
+    }
+
+    /// Consume the authenticator and compare the tag with `tag` in constant time
+    ///
+    /// ## Arguments
+    /// - `tag` - The expected 16-byte tag
+    ///
+    /// ## Returns
+    /// `Error::InvalidTag` if the tags differ
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::poly1305::Poly1305;
+    ///
+    /// let key = [7u8; 32];
+    /// let mut mac = Poly1305::new(&key);
+    /// mac.update(b"message");
+    /// let tag = mac.finalize();
+    ///
+    /// let mut mac = Poly1305::new(&key);
+    /// mac.update(b"message");
+    /// assert!(mac.verify(&tag).is_ok());
+    /// ```
+    pub fn verify(self, tag: &[u8; 16]) -> Result<(), Error> {
+        let expected = self.finalize();
+
+        let mut diff = 0u8;
+        for (x, y) in expected.iter().zip(tag.iter()) {
+            diff |= x ^ y;
+        }
+
+        if core::hint::black_box(diff) == 0 {
+            Ok(())
+        } else {
+            Err(Error::InvalidTag)
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! # ChaCha random number generator
+//!
+//! A deterministic, seedable CSPRNG that outputs the ChaCha keystream for a 256-bit seed. It uses
+//! the original ChaCha layout with a 64-bit block counter and a 64-bit stream number in place of
+//! the nonce, so the output is identical to `rand_chacha` for the same seed, stream and position.
+//!
+//! Available with the `rand_core` feature, which also re-exports the `rand_core` crate.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::rng::ChaChaRng;
+//! use chacha20imp::rand_core::{RngCore, SeedableRng};
+//!
+//! let mut rng = ChaChaRng::from_seed([42u8; 32]);
+//! let mut key = [0u8; 32];
+//! rng.fill_bytes(&mut key);
+//! let roll = rng.next_u32() % 6 + 1;
+//! println!("Key: {:?}, roll: {}", key, roll);
+//! ```
+
+use crate::chacha20::{ChaChaImpl, CONSTANTS};
+use rand_core::{CryptoRng, Error, RngCore, SeedableRng};
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Number of 32-bit words in a keystream block
+const BLOCK_WORDS: usize = 16;
+
+/// ChaCha random number generator with `ROUNDS` rounds
+#[derive(Clone)]
+pub struct ChaChaXRng<const ROUNDS: usize> {
+    state: [u32; 16],
+    block: [u8; 64],
+    /// Index of the next unused word in `block`, `BLOCK_WORDS` when it is used up
+    index: usize,
+}
+
+/// ChaCha RNG with 8 rounds, matching `rand_chacha::ChaCha8Rng`
+pub type ChaCha8Rng = ChaChaXRng<8>;
+
+/// ChaCha RNG with 12 rounds, matching `rand_chacha::ChaCha12Rng`
+pub type ChaCha12Rng = ChaChaXRng<12>;
+
+/// ChaCha RNG with 20 rounds, matching `rand_chacha::ChaCha20Rng`
+pub type ChaCha20Rng = ChaChaXRng<20>;
+
+/// The default ChaCha RNG with 20 rounds
+pub type ChaChaRng = ChaCha20Rng;
+
+impl<const ROUNDS: usize> ChaChaXRng<ROUNDS> {
+    /// Get the seed the generator was created with
+    pub fn get_seed(&self) -> [u8; 32] {
+        let mut seed = [0u8; 32];
+        for (chunk, word) in seed.chunks_exact_mut(4).zip(self.state[4..12].iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        seed
+    }
+
+    /// Get the stream number
+    pub fn get_stream(&self) -> u64 {
+        (self.state[15] as u64) << 32 | self.state[14] as u64
+    }
+
+    /// Select one of the 2^64 independent streams of the seed
+    ///
+    /// The word position is kept, so the next output comes from the same position in the new
+    /// stream.
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::rng::ChaChaRng;
+    /// use chacha20imp::rand_core::{RngCore, SeedableRng};
+    ///
+    /// let mut a = ChaChaRng::from_seed([0u8; 32]);
+    /// let mut b = ChaChaRng::from_seed([0u8; 32]);
+    /// b.set_stream(1);
+    /// assert_ne!(a.next_u64(), b.next_u64());
+    /// ```
+    pub fn set_stream(&mut self, stream: u64) {
+        let word_pos = self.get_word_pos();
+        self.state[14] = stream as u32;
+        self.state[15] = (stream >> 32) as u32;
+        self.set_word_pos(word_pos);
+    }
+
+    /// Get the position of the next output word in the stream
+    pub fn get_word_pos(&self) -> u128 {
+        let block = if self.index == BLOCK_WORDS {
+            self.counter()
+        } else {
+            self.counter().wrapping_sub(1)
+        };
+
+        block as u128 * BLOCK_WORDS as u128 + (self.index % BLOCK_WORDS) as u128
+    }
+
+    /// Set the position of the next output word in the stream
+    ///
+    /// The stream is 2^68 words long, so higher bits of `word_pos` are ignored.
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::rng::ChaChaRng;
+    /// use chacha20imp::rand_core::{RngCore, SeedableRng};
+    ///
+    /// let mut rng = ChaChaRng::from_seed([0u8; 32]);
+    /// let first = rng.next_u32();
+    /// rng.set_word_pos(0);
+    /// assert_eq!(rng.next_u32(), first);
+    /// ```
+    pub fn set_word_pos(&mut self, word_pos: u128) {
+        self.set_counter((word_pos / BLOCK_WORDS as u128) as u64);
+        self.index = BLOCK_WORDS;
+
+        let index = (word_pos % BLOCK_WORDS as u128) as usize;
+        if index != 0 {
+            self.next_block();
+            self.index = index;
+        }
+    }
+
+    fn counter(&self) -> u64 {
+        (self.state[13] as u64) << 32 | self.state[12] as u64
+    }
+
+    fn set_counter(&mut self, counter: u64) {
+        self.state[12] = counter as u32;
+        self.state[13] = (counter >> 32) as u32;
+    }
+
+    /// Generate the block at the current counter and advance the counter
+    fn next_block(&mut self) {
+        ChaChaImpl::<ROUNDS>::chacha20_keystream_block(&self.state, &mut self.block);
+        self.set_counter(self.counter().wrapping_add(1));
+        self.index = 0;
+    }
+
+    fn word(&self, index: usize) -> [u8; 4] {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&self.block[4 * index..4 * index + 4]);
+        word
+    }
+}
+
+impl<const ROUNDS: usize> SeedableRng for ChaChaXRng<ROUNDS> {
+    type Seed = [u8; 32];
+
+    /// Create a generator on stream 0 with the seed as the ChaCha key
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut state = [0u32; 16];
+        state[0..4].copy_from_slice(&CONSTANTS);
+        for (word, chunk) in state[4..12].iter_mut().zip(seed.chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        ChaChaXRng {
+            state,
+            block: [0u8; 64],
+            index: BLOCK_WORDS,
+        }
+    }
+}
+
+impl<const ROUNDS: usize> RngCore for ChaChaXRng<ROUNDS> {
+    fn next_u32(&mut self) -> u32 {
+        if self.index == BLOCK_WORDS {
+            self.next_block();
+        }
+
+        let word = self.word(self.index);
+        self.index += 1;
+        u32::from_le_bytes(word)
+    }
+
+    /// Two consecutive words, the first one in the low half
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        high << 32 | low
+    }
+
+    /// Fill `dest` with whole words of output
+    ///
+    /// A trailing partial word is discarded, so the next output starts on a fresh word.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            if self.index == BLOCK_WORDS {
+                self.next_block();
+            }
+
+            chunk.copy_from_slice(&self.word(self.index)[..chunk.len()]);
+            self.index += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<const ROUNDS: usize> CryptoRng for ChaChaXRng<ROUNDS> {}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> Zeroize for ChaChaXRng<ROUNDS> {
+    /// Wipe the seed, stream, position and buffered output
+    fn zeroize(&mut self) {
+        self.state.zeroize();
+        self.block.zeroize();
+        self.index.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> Drop for ChaChaXRng<ROUNDS> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> ZeroizeOnDrop for ChaChaXRng<ROUNDS> {}
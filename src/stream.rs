@@ -0,0 +1,586 @@
+//! # Streaming AEAD
+//!
+//! Authenticated encryption of long or unbounded messages with the STREAM construction of
+//! Hoang, Reyhanitabar, Rogaway and Vizár, built on ChaCha20-Poly1305. The plaintext is split into
+//! segments that are encrypted and authenticated on their own, each with the nonce
+//!
+//! ```text
+//! nonce prefix (7 bytes) || segment counter (4 bytes, big-endian) || last-segment flag (1 byte)
+//! ```
+//!
+//! A segment only decrypts at its original position, so reordered, duplicated or dropped segments
+//! are rejected, and only the final segment has the flag set, so a stream cut at a segment
+//! boundary or extended past its end is rejected as well. The layout matches the `StreamBE32`
+//! flavour of the RustCrypto `aead` crate.
+//!
+//! The nonce prefix must never be reused with the same key. It is small enough to be chosen at
+//! random only for a moderate number of streams per key; derive a fresh key per stream otherwise,
+//! or use the XChaCha20-Poly1305 variant from `new_x`, whose 19-byte prefix can always be random.
+//!
+//! There are two interfaces:
+//! - [`StreamEncryptor`] and [`StreamDecryptor`] process one segment per call, with the caller
+//!   choosing the segment boundaries
+//! - with the `std` feature, `SealingWriter` and `OpeningReader` cut the data into segments of
+//!   a fixed size behind the `Write` and `Read` traits
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::stream::{StreamDecryptor, StreamEncryptor};
+//!
+//! let key = [0x42u8; 32];
+//! let prefix = [0x24u8; 7];
+//!
+//! let mut encryptor = StreamEncryptor::new(&key, &prefix);
+//! let mut first = *b"Hello, ";
+//! let mut last = *b"world!";
+//! let first_tag = encryptor.encrypt_next_in_place(b"", &mut first).unwrap();
+//! let last_tag = encryptor.encrypt_last_in_place(b"", &mut last);
+//!
+//! let mut decryptor = StreamDecryptor::new(&key, &prefix);
+//! decryptor.decrypt_next_in_place(b"", &mut first, &first_tag).unwrap();
+//! decryptor.decrypt_last_in_place(b"", &mut last, &last_tag).unwrap();
+//! assert_eq!(&first, b"Hello, ");
+//! assert_eq!(&last, b"world!");
+//! ```
+
+use crate::chacha20poly1305::{ChaCha20Poly1305, TAG_SIZE};
+use crate::wipe;
+use crate::xchacha20::hchacha20;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Size in bytes of the nonce prefix
+pub const NONCE_PREFIX_SIZE: usize = 7;
+
+/// Size in bytes of the nonce prefix of the XChaCha20-Poly1305 variant
+pub const X_NONCE_PREFIX_SIZE: usize = 19;
+
+/// Default amount of plaintext per segment used by the `Read`/`Write` adapters
+pub const DEFAULT_SEGMENT_SIZE: usize = 64 * 1024;
+
+/// Errors returned by the streaming AEAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A segment failed authentication, or is out of order, truncated or not the expected last one
+    InvalidTag,
+    /// The stream ended before its last segment
+    Truncated,
+    /// All segment numbers but the one kept for the last segment have been used
+    CounterOverflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidTag => write!(f, "Segment authentication failed"),
+            Error::Truncated => write!(f, "Stream ended before its last segment"),
+            Error::CounterOverflow => write!(f, "Segment counter overflowed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Segment counter and nonce construction shared by both directions
+struct Segments {
+    aead: ChaCha20Poly1305,
+    prefix: [u8; NONCE_PREFIX_SIZE],
+    /// Number of the next segment
+    counter: u32,
+}
+
+impl Segments {
+    fn new(key: &[u8; 32], prefix: &[u8; NONCE_PREFIX_SIZE]) -> Self {
+        Segments {
+            aead: ChaCha20Poly1305::new(key),
+            prefix: *prefix,
+            counter: 0,
+        }
+    }
+
+    /// XChaCha20-Poly1305 segments, expressed as ChaCha20-Poly1305 under an HChaCha20 subkey
+    fn new_x(key: &[u8; 32], prefix: &[u8; X_NONCE_PREFIX_SIZE]) -> Self {
+        let mut input = [0u8; 16];
+        input.copy_from_slice(&prefix[..16]);
+
+        // The inner nonce is 4 zero bytes followed by the last 8 bytes of the 24-byte nonce
+        let mut inner_prefix = [0u8; NONCE_PREFIX_SIZE];
+        inner_prefix[4..].copy_from_slice(&prefix[16..]);
+
+        let mut subkey = hchacha20(key, &input);
+        let segments = Segments::new(&subkey, &inner_prefix);
+
+        wipe(&mut subkey);
+
+        segments
+    }
+
+    fn nonce(&self, last: bool) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..NONCE_PREFIX_SIZE].copy_from_slice(&self.prefix);
+        nonce[NONCE_PREFIX_SIZE..11].copy_from_slice(&self.counter.to_be_bytes());
+        nonce[11] = last as u8;
+        nonce
+    }
+
+    /// Nonce of the next segment that is not the last one
+    ///
+    /// The highest counter value is kept for the last segment, so a stream can always be ended.
+    fn next_nonce(&self) -> Result<[u8; 12], Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::CounterOverflow);
+        }
+
+        Ok(self.nonce(false))
+    }
+}
+
+/// Encrypts a stream one segment at a time
+pub struct StreamEncryptor {
+    segments: Segments,
+}
+
+impl StreamEncryptor {
+    /// Start a stream with a key and a nonce prefix
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key
+    /// - `prefix` - A 56-bit nonce prefix, which must never be reused with the same key
+    pub fn new(key: &[u8; 32], prefix: &[u8; NONCE_PREFIX_SIZE]) -> Self {
+        StreamEncryptor {
+            segments: Segments::new(key, prefix),
+        }
+    }
+
+    /// Start a stream of XChaCha20-Poly1305 segments, whose 192-bit nonces are the 152-bit
+    /// prefix followed by the counter and the flag
+    ///
+    /// The longer prefix can be chosen at random for any number of streams per key.
+    pub fn new_x(key: &[u8; 32], prefix: &[u8; X_NONCE_PREFIX_SIZE]) -> Self {
+        StreamEncryptor {
+            segments: Segments::new_x(key, prefix),
+        }
+    }
+
+    /// Encrypt a segment that is not the last one in place
+    ///
+    /// ## Arguments
+    /// - `aad` - Associated data for this segment
+    /// - `buffer` - The plaintext, replaced by the ciphertext
+    ///
+    /// ## Returns
+    /// The 16-byte tag of the segment, or `Error::CounterOverflow` after 2^32 - 1 segments
+    ///
+    /// ## Panics
+    /// If the buffer is longer than [`MAX_MESSAGE_SIZE`](crate::chacha20poly1305::MAX_MESSAGE_SIZE)
+    pub fn encrypt_next_in_place(&mut self, aad: &[u8], buffer: &mut [u8]) -> Result<[u8; TAG_SIZE], Error> {
+        let nonce = self.segments.next_nonce()?;
+        let tag = self.segments.aead.encrypt_in_place_detached(&nonce, aad, buffer);
+        self.segments.counter += 1;
+        Ok(tag)
+    }
+
+    /// Encrypt the last segment in place, which ends the stream
+    ///
+    /// The last segment may be empty.
+    ///
+    /// ## Arguments
+    /// - `aad` - Associated data for this segment
+    /// - `buffer` - The plaintext, replaced by the ciphertext
+    ///
+    /// ## Returns
+    /// The 16-byte tag of the segment
+    ///
+    /// ## Panics
+    /// If the buffer is longer than [`MAX_MESSAGE_SIZE`](crate::chacha20poly1305::MAX_MESSAGE_SIZE)
+    pub fn encrypt_last_in_place(self, aad: &[u8], buffer: &mut [u8]) -> [u8; TAG_SIZE] {
+        let nonce = self.segments.nonce(true);
+        self.segments.aead.encrypt_in_place_detached(&nonce, aad, buffer)
+    }
+
+    /// Encrypt a segment that is not the last one and append its tag
+    ///
+    /// ## Returns
+    /// The ciphertext followed by the tag, or `Error::CounterOverflow` after 2^32 - 1 segments
+    #[cfg(feature = "alloc")]
+    pub fn encrypt_next(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut buffer = plaintext.to_vec();
+        let tag = self.encrypt_next_in_place(aad, &mut buffer)?;
+        buffer.extend_from_slice(&tag);
+        Ok(buffer)
+    }
+
+    /// Encrypt the last segment and append its tag
+    ///
+    /// ## Returns
+    /// The ciphertext followed by the tag
+    #[cfg(feature = "alloc")]
+    pub fn encrypt_last(self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut buffer = plaintext.to_vec();
+        let tag = self.encrypt_last_in_place(aad, &mut buffer);
+        buffer.extend_from_slice(&tag);
+        buffer
+    }
+}
+
+/// Decrypts a stream one segment at a time
+pub struct StreamDecryptor {
+    segments: Segments,
+}
+
+impl StreamDecryptor {
+    /// Start decrypting a stream with the key and nonce prefix it was encrypted with
+    pub fn new(key: &[u8; 32], prefix: &[u8; NONCE_PREFIX_SIZE]) -> Self {
+        StreamDecryptor {
+            segments: Segments::new(key, prefix),
+        }
+    }
+
+    /// Start decrypting a stream of XChaCha20-Poly1305 segments
+    pub fn new_x(key: &[u8; 32], prefix: &[u8; X_NONCE_PREFIX_SIZE]) -> Self {
+        StreamDecryptor {
+            segments: Segments::new_x(key, prefix),
+        }
+    }
+
+    /// Verify and decrypt a segment that is not the last one in place
+    ///
+    /// The buffer is left untouched and the decryptor stays at the same segment when
+    /// authentication fails.
+    ///
+    /// ## Arguments
+    /// - `aad` - Associated data for this segment
+    /// - `buffer` - The ciphertext, replaced by the plaintext
+    /// - `tag` - The 16-byte tag of the segment
+    ///
+    /// ## Returns
+    /// `Error::InvalidTag` if authentication fails, which includes a segment that is out of
+    /// order or is actually the last one
+    pub fn decrypt_next_in_place(&mut self, aad: &[u8], buffer: &mut [u8], tag: &[u8; TAG_SIZE]) -> Result<(), Error> {
+        let nonce = self.segments.next_nonce()?;
+        self.segments
+            .aead
+            .decrypt_in_place_detached(&nonce, aad, buffer, tag)
+            .map_err(|_| Error::InvalidTag)?;
+        self.segments.counter += 1;
+        Ok(())
+    }
+
+    /// Verify and decrypt the last segment in place, which ends the stream
+    ///
+    /// ## Returns
+    /// `Error::InvalidTag` if authentication fails, which includes a segment that is out of
+    /// order or is not the last one
+    pub fn decrypt_last_in_place(self, aad: &[u8], buffer: &mut [u8], tag: &[u8; TAG_SIZE]) -> Result<(), Error> {
+        let nonce = self.segments.nonce(true);
+        self.segments
+            .aead
+            .decrypt_in_place_detached(&nonce, aad, buffer, tag)
+            .map_err(|_| Error::InvalidTag)
+    }
+
+    /// Verify and decrypt a segment that is not the last one, given with its tag appended
+    ///
+    /// ## Returns
+    /// The plaintext, `Error::Truncated` if the input is shorter than a tag, or
+    /// `Error::InvalidTag` if authentication fails
+    #[cfg(feature = "alloc")]
+    pub fn decrypt_next(&mut self, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let (mut buffer, tag) = split_tag(ciphertext)?;
+        self.decrypt_next_in_place(aad, &mut buffer, &tag)?;
+        Ok(buffer)
+    }
+
+    /// Verify and decrypt the last segment, given with its tag appended
+    ///
+    /// ## Returns
+    /// The plaintext, `Error::Truncated` if the input is shorter than a tag, or
+    /// `Error::InvalidTag` if authentication fails
+    #[cfg(feature = "alloc")]
+    pub fn decrypt_last(self, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let (mut buffer, tag) = split_tag(ciphertext)?;
+        self.decrypt_last_in_place(aad, &mut buffer, &tag)?;
+        Ok(buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn split_tag(ciphertext: &[u8]) -> Result<(Vec<u8>, [u8; TAG_SIZE]), Error> {
+    if ciphertext.len() < TAG_SIZE {
+        return Err(Error::Truncated);
+    }
+
+    let (body, tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+    let mut tag_bytes = [0u8; TAG_SIZE];
+    tag_bytes.copy_from_slice(tag);
+    Ok((body.to_vec(), tag_bytes))
+}
+
+#[cfg(feature = "std")]
+mod adapters {
+    use super::{Error, StreamDecryptor, StreamEncryptor, DEFAULT_SEGMENT_SIZE};
+    use crate::chacha20poly1305::TAG_SIZE;
+    use std::io::{self, Read, Write};
+    use std::vec::Vec;
+
+    fn invalid_data(error: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+
+    /// Writer that encrypts data into segments of a fixed size and writes them to the inner writer
+    ///
+    /// Each segment is written as its ciphertext followed by its tag. [`SealingWriter::finish`]
+    /// must be called to write the last segment; a stream that is dropped without it is rejected
+    /// by the reader as truncated.
+    pub struct SealingWriter<W: Write> {
+        inner: W,
+        encryptor: Option<StreamEncryptor>,
+        segment_size: usize,
+        /// Plaintext of the segment being filled
+        plaintext: Vec<u8>,
+        /// Sealed segment waiting to be written, from `written` on
+        output: Vec<u8>,
+        written: usize,
+    }
+
+    impl<W: Write> SealingWriter<W> {
+        /// Wrap `inner`, encrypting into segments of [`DEFAULT_SEGMENT_SIZE`] bytes
+        pub fn new(inner: W, encryptor: StreamEncryptor) -> Self {
+            SealingWriter {
+                inner,
+                encryptor: Some(encryptor),
+                segment_size: DEFAULT_SEGMENT_SIZE,
+                plaintext: Vec::new(),
+                output: Vec::new(),
+                written: 0,
+            }
+        }
+
+        /// Set the amount of plaintext per segment, which the reader must use as well
+        ///
+        /// ## Panics
+        /// If `segment_size` is 0
+        pub fn with_segment_size(mut self, segment_size: usize) -> Self {
+            assert!(segment_size > 0, "Segment size must not be 0");
+            self.segment_size = segment_size;
+            self
+        }
+
+        /// Get a reference to the inner writer
+        pub fn get_ref(&self) -> &W {
+            &self.inner
+        }
+
+        /// Encrypt the buffered plaintext as the last segment, write everything and flush
+        ///
+        /// ## Returns
+        /// The inner writer
+        pub fn finish(mut self) -> io::Result<W> {
+            self.write_output()?;
+            if let Some(encryptor) = self.encryptor.take() {
+                let tag = encryptor.encrypt_last_in_place(b"", &mut self.plaintext);
+                self.seal(&tag);
+                self.write_output()?;
+            }
+
+            self.inner.flush()?;
+            Ok(self.inner)
+        }
+
+        /// Move the encrypted plaintext and its tag to the output
+        fn seal(&mut self, tag: &[u8; TAG_SIZE]) {
+            self.output.clear();
+            self.output.append(&mut self.plaintext);
+            self.output.extend_from_slice(tag);
+            self.written = 0;
+        }
+
+        /// Write the sealed segment, keeping what is left of it when the inner writer fails
+        fn write_output(&mut self) -> io::Result<()> {
+            while self.written < self.output.len() {
+                match self.inner.write(&self.output[self.written..]) {
+                    Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                    Ok(written) => self.written += written,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                    Err(error) => return Err(error),
+                }
+            }
+
+            self.output.clear();
+            self.written = 0;
+            Ok(())
+        }
+    }
+
+    impl<W: Write> Write for SealingWriter<W> {
+        /// Buffer up to a segment of plaintext, first writing out the previous segment once more
+        /// data shows it was not the last one
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            self.write_output()?;
+            if self.plaintext.len() == self.segment_size {
+                let encryptor = self.encryptor.as_mut().ok_or(io::ErrorKind::BrokenPipe)?;
+                let tag = encryptor.encrypt_next_in_place(b"", &mut self.plaintext).map_err(invalid_data)?;
+                self.seal(&tag);
+                self.write_output()?;
+            }
+
+            let len = buf.len().min(self.segment_size - self.plaintext.len());
+            self.plaintext.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        /// Write the segments sealed so far and flush the inner writer
+        ///
+        /// The segment being filled stays buffered, as it can only be encrypted once it is known
+        /// whether it is the last one.
+        fn flush(&mut self) -> io::Result<()> {
+            self.write_output()?;
+            self.inner.flush()
+        }
+    }
+
+    /// Reader that verifies and decrypts a stream written by [`SealingWriter`]
+    ///
+    /// Plaintext is only returned once its whole segment has been authenticated. Reaching the end
+    /// of the inner reader without a valid last segment is an `InvalidData` error, so a truncated
+    /// stream never reads as complete.
+    pub struct OpeningReader<R: Read> {
+        inner: R,
+        decryptor: Option<StreamDecryptor>,
+        segment_size: usize,
+        /// Ciphertext of the next segment, plus one byte of lookahead to spot the last one
+        input: Vec<u8>,
+        /// Plaintext of the current segment, served from `position` on
+        plaintext: Vec<u8>,
+        position: usize,
+        error: Option<Error>,
+    }
+
+    impl<R: Read> OpeningReader<R> {
+        /// Wrap `inner`, expecting segments of [`DEFAULT_SEGMENT_SIZE`] bytes
+        pub fn new(inner: R, decryptor: StreamDecryptor) -> Self {
+            OpeningReader {
+                inner,
+                decryptor: Some(decryptor),
+                segment_size: DEFAULT_SEGMENT_SIZE,
+                input: Vec::new(),
+                plaintext: Vec::new(),
+                position: 0,
+                error: None,
+            }
+        }
+
+        /// Set the amount of plaintext per segment the stream was written with
+        ///
+        /// ## Panics
+        /// If `segment_size` is 0
+        pub fn with_segment_size(mut self, segment_size: usize) -> Self {
+            assert!(segment_size > 0, "Segment size must not be 0");
+            self.segment_size = segment_size;
+            self
+        }
+
+        /// Get a reference to the inner reader
+        pub fn get_ref(&self) -> &R {
+            &self.inner
+        }
+
+        /// Unwrap the inner reader
+        pub fn into_inner(self) -> R {
+            self.inner
+        }
+
+        /// Verify and decrypt the segment in `input`, which holds `filled` bytes
+        ///
+        /// A full segment followed by at least one more byte is not the last one.
+        fn open_segment(&mut self, filled: usize) -> Result<(), Error> {
+            let sealed_size = self.segment_size + TAG_SIZE;
+            let decryptor = self.decryptor.take().ok_or(Error::Truncated)?;
+
+            if filled > sealed_size {
+                let mut decryptor = decryptor;
+                let lookahead = self.input[sealed_size];
+                self.open(sealed_size, |buffer, tag| decryptor.decrypt_next_in_place(b"", buffer, tag))?;
+                self.input.push(lookahead);
+                self.decryptor = Some(decryptor);
+                Ok(())
+            } else if filled >= TAG_SIZE {
+                self.open(filled, |buffer, tag| decryptor.decrypt_last_in_place(b"", buffer, tag))
+            } else {
+                Err(Error::Truncated)
+            }
+        }
+
+        /// Split the first `len` bytes of input into ciphertext and tag and open them
+        fn open(
+            &mut self,
+            len: usize,
+            open: impl FnOnce(&mut [u8], &[u8; TAG_SIZE]) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            let mut tag = [0u8; TAG_SIZE];
+            tag.copy_from_slice(&self.input[len - TAG_SIZE..len]);
+
+            self.input.truncate(len - TAG_SIZE);
+            open(&mut self.input, &tag)?;
+
+            core::mem::swap(&mut self.plaintext, &mut self.input);
+            self.input.clear();
+            self.position = 0;
+            Ok(())
+        }
+
+        /// Read until `input` holds `len` bytes or the inner reader ends
+        fn fill_input(&mut self, len: usize) -> io::Result<usize> {
+            let mut filled = self.input.len();
+            self.input.resize(len, 0);
+            while filled < len {
+                match self.inner.read(&mut self.input[filled..]) {
+                    Ok(0) => break,
+                    Ok(read) => filled += read,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                    Err(error) => {
+                        self.input.truncate(filled);
+                        return Err(error);
+                    }
+                }
+            }
+
+            self.input.truncate(filled);
+            Ok(filled)
+        }
+    }
+
+    impl<R: Read> Read for OpeningReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(error) = self.error {
+                return Err(invalid_data(error));
+            }
+
+            while self.position == self.plaintext.len() {
+                if self.decryptor.is_none() || buf.is_empty() {
+                    return Ok(0);
+                }
+
+                let filled = self.fill_input(self.segment_size + TAG_SIZE + 1)?;
+                if let Err(error) = self.open_segment(filled) {
+                    self.error = Some(error);
+                    return Err(invalid_data(error));
+                }
+            }
+
+            let len = buf.len().min(self.plaintext.len() - self.position);
+            buf[..len].copy_from_slice(&self.plaintext[self.position..self.position + len]);
+            self.position += len;
+            Ok(len)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use adapters::{OpeningReader, SealingWriter};
@@ -0,0 +1,171 @@
+//! # XChaCha20 Stream Cipher
+//!
+//! XChaCha20 extends the ChaCha20 nonce to 192 bits, which is long enough to be picked at random
+//! for every message. HChaCha20 derives a subkey from the key and the first 128 bits of the nonce,
+//! and the remaining 64 bits become the nonce of a regular ChaCha20 instance using that subkey.
+//!
+//! ## Example
+//! ```rust
+//! use chacha20imp::chacha20::ChaCha20;
+//! use chacha20imp::xchacha20::XChaCha20;
+//!
+//! let key = [0u8; 32];
+//! let nonce = [0u8; 24];
+//! let mut cipher = XChaCha20::new(&key, &nonce);
+//! let mut buf = [0u8; 64];
+//! cipher.apply_keystream(&mut buf);
+//! println!("Keystream: {:?}", buf);
+//! ```
+
+use crate::backends::Backend;
+use crate::chacha20::{ChaCha20, ChaCha20Impl, Error, CONSTANTS};
+use crate::wipe;
+
+/// Derive a 256-bit subkey with HChaCha20
+///
+/// The state is built like a ChaCha20 block with the 128-bit `input` in place of the counter and
+/// nonce. After the 20 rounds, words 0..4 and 12..16 form the subkey.
+///
+/// ## Arguments
+/// - `key` - A 256-bit key
+/// - `input` - The first 128 bits of an XChaCha20 nonce
+///
+/// ## Returns
+/// The 32-byte subkey
+///
+/// ## Example
+/// ```
+/// use chacha20imp::xchacha20::hchacha20;
+/// let subkey = hchacha20(&[0u8; 32], &[0u8; 16]);
+/// ```
+pub fn hchacha20(key: &[u8; 32], input: &[u8; 16]) -> [u8; 32] {
+    let mut state = [0u32; 16];
+    state[0..4].copy_from_slice(&CONSTANTS);
+
+    for (word, chunk) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    for (word, chunk) in state[12..16].iter_mut().zip(input.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+
+    ChaCha20Impl::rounds(&mut state);
+
+    let mut subkey = [0u8; 32];
+    for (chunk, word) in subkey.chunks_exact_mut(4).zip(state[0..4].iter().chain(state[12..16].iter())) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+
+    wipe(&mut state);
+
+    subkey
+}
+
+#[derive(Clone)]
+pub struct XChaCha20 {
+    inner: ChaCha20Impl,
+}
+
+impl XChaCha20 {
+    /// Create a new instance from a fixed-size key and nonce, which cannot fail
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key
+    /// - `nonce` - A 192-bit nonce
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::xchacha20::XChaCha20;
+    /// let xchacha = XChaCha20::from_key_nonce(&[0u8; 32], &[0u8; 24]);
+    /// ```
+    pub fn from_key_nonce(key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        let mut input = [0u8; 16];
+        input.copy_from_slice(&nonce[..16]);
+
+        let mut subkey = hchacha20(key, &input);
+
+        let mut new_nonce = [0u8; 12];
+        new_nonce[4..].copy_from_slice(&nonce[16..]);
+
+        let inner = ChaCha20Impl::from_key_nonce(&subkey, &new_nonce);
+
+        wipe(&mut subkey);
+
+        XChaCha20 { inner }
+    }
+
+    /// Select the backend used to generate the keystream, see [`ChaCha20Impl::set_backend`]
+    ///
+    /// ## Panics
+    /// If the backend is not supported by the current CPU
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.inner.set_backend(backend);
+    }
+}
+
+impl ChaCha20 for XChaCha20 {
+    const NONCE_SIZE: usize = 24;
+
+    /// Create a new XChaCha20 instance
+    ///
+    /// ## Arguments
+    /// - `key` - A 256-bit key as a slice of 32 u8 values
+    /// - `nonce` - A 192-bit nonce as a slice of 24 u8 values
+    ///
+    /// ## Returns
+    /// A new XChaCha20 instance
+    ///
+    /// ## Panics
+    /// If the key or nonce has the wrong length
+    ///
+    /// ## Example
+    /// ```
+    /// use chacha20imp::chacha20::ChaCha20;
+    /// use chacha20imp::xchacha20::XChaCha20;
+    /// let xchacha = XChaCha20::new(&[0u8; 32], &[0u8; 24]);
+    /// ```
+    fn new(key: &[u8], nonce: &[u8]) -> Self {
+        match (key.try_into(), nonce.try_into()) {
+            (Ok(key), Ok(nonce)) => Self::from_key_nonce(key, nonce),
+            (Err(_), _) => panic!("Key must be 256 bits (32 u8 values)"),
+            (_, Err(_)) => panic!("Nonce must be 192 bits (24 u8 values)"),
+        }
+    }
+
+    /// Apply the keystream to the input
+    ///
+    /// ## Arguments
+    /// - `input` - A mutable slice of u8 values to apply the keystream to
+    fn apply_keystream(&mut self, input: &mut [u8]) {
+        self.inner.apply_keystream(input);
+    }
+
+    /// Seek to a specific block in the keystream
+    ///
+    /// ## Arguments
+    /// a `pos` u32 value to seek to
+    fn seek(&mut self, pos: u32) {
+        self.inner.seek(pos);
+    }
+
+    /// Seek to a byte position in the keystream
+    ///
+    /// ## Arguments
+    /// - `pos` - A u64 byte offset from the start of the keystream
+    ///
+    /// ## Returns
+    /// `Error::PositionOutOfRange` if `pos` is past the end of the keystream
+    fn seek_pos(&mut self, pos: u64) -> Result<(), Error> {
+        self.inner.seek_pos(pos)
+    }
+
+    /// Get the current byte position in the keystream
+    fn current_pos(&self) -> u64 {
+        self.inner.current_pos()
+    }
+
+    /// Get the number of keystream bytes left before the block counter wraps around
+    fn remaining_keystream(&self) -> u64 {
+        self.inner.remaining_keystream()
+    }
+}
@@ -1,5 +1,33 @@
 #[cfg(test)]
 mod tests {
+    use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+
+    /// Encrypts `plaintext` split at every position and in chunks of every size, and checks
+    /// each result against the expected one-shot `ciphertext`.
+    fn assert_every_split(key: &[u8], nonce: &[u8], counter: u32, plaintext: &[u8], ciphertext: &[u8]) {
+        for split in 0..=plaintext.len() {
+            let mut cipher = ChaCha20Impl::new(key, nonce);
+            cipher.seek(counter);
+
+            let mut buf = plaintext.to_vec();
+            let (head, tail) = buf.split_at_mut(split);
+            cipher.apply_keystream(head);
+            cipher.apply_keystream(tail);
+            assert_eq!(&buf[..], ciphertext, "split at {}", split);
+        }
+
+        for chunk_size in 1..=plaintext.len() {
+            let mut cipher = ChaCha20Impl::new(key, nonce);
+            cipher.seek(counter);
+
+            let mut buf = plaintext.to_vec();
+            for chunk in buf.chunks_mut(chunk_size) {
+                cipher.apply_keystream(chunk);
+            }
+            assert_eq!(&buf[..], ciphertext, "chunk size {}", chunk_size);
+        }
+    }
+
     mod from_rust_crypto {
         use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
         use hex_literal::hex;
@@ -70,6 +98,12 @@ mod tests {
             cipher.apply_keystream(&mut buf);
             assert_eq!(&buf[..], &CIPHERTEXT[..]);
         }
+
+        #[test]
+        fn chacha20_encryption_every_split() {
+            // Block 0 is skipped by seeking, as in the RFC 8439 section 2.4.2 vector
+            super::assert_every_split(&KEY, &IV, 1, &PLAINTEXT, &CIPHERTEXT);
+        }
     }
 
     mod keystream_tests {
@@ -332,6 +366,8 @@ mod tests {
 
             cipher.apply_keystream(&mut buf);
             assert_eq!(&buf[..], &CIPHERTEXT[..]);
+
+            super::assert_every_split(&KEY, &NONCE, 1, &PLAINTEXT, &CIPHERTEXT);
         }
 
         #[test]
@@ -381,6 +417,3584 @@ mod tests {
 
             cipher.apply_keystream(&mut buf);
             assert_eq!(&buf[..], &CIPHERTEXT[..]);
+
+            super::assert_every_split(&KEY, &NONCE, 42, &PLAINTEXT, &CIPHERTEXT);
+        }
+    }
+
+    mod seek_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl, Error};
+        use hex_literal::hex;
+
+        const KEY: [u8; 32] =
+            hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+
+        const NONCE: [u8; 12] = hex!("000000090000004a00000000");
+
+        fn keystream(len: usize) -> Vec<u8> {
+            let mut cipher = ChaCha20Impl::new(&KEY, &NONCE);
+            let mut buf = vec![0u8; len];
+            cipher.apply_keystream(&mut buf);
+            buf
+        }
+
+        #[test]
+        fn seek_pos_matches_one_shot_keystream() {
+            let expected = keystream(300);
+
+            for pos in 0..expected.len() {
+                let mut cipher = ChaCha20Impl::new(&KEY, &NONCE);
+                cipher.seek_pos(pos as u64).unwrap();
+                assert_eq!(cipher.current_pos(), pos as u64);
+
+                let mut buf = vec![0u8; expected.len() - pos];
+                cipher.apply_keystream(&mut buf);
+                assert_eq!(&buf[..], &expected[pos..], "seek to {}", pos);
+                assert_eq!(cipher.current_pos(), expected.len() as u64);
+            }
+        }
+
+        #[test]
+        fn seek_pos_on_block_boundary_matches_seek() {
+            let mut by_block = ChaCha20Impl::new(&KEY, &NONCE);
+            by_block.seek(3);
+
+            let mut by_byte = ChaCha20Impl::new(&KEY, &NONCE);
+            by_byte.seek_pos(3 * 64).unwrap();
+
+            assert_eq!(by_block.current_pos(), by_byte.current_pos());
+
+            let mut a = [0u8; 100];
+            let mut b = [0u8; 100];
+            by_block.apply_keystream(&mut a);
+            by_byte.apply_keystream(&mut b);
+            assert_eq!(a, b);
+        }
+
+        #[test]
+        fn current_pos_tracks_partial_blocks() {
+            let mut cipher = ChaCha20Impl::new(&KEY, &NONCE);
+            assert_eq!(cipher.current_pos(), 0);
+
+            let mut total = 0;
+            for len in [7, 8, 64, 1, 120, 0, 200] {
+                cipher.apply_keystream(&mut vec![0u8; len]);
+                total += len as u64;
+                assert_eq!(cipher.current_pos(), total);
+            }
+
+            cipher.seek(5);
+            assert_eq!(cipher.current_pos(), 5 * 64);
+        }
+
+        #[test]
+        fn seek_pos_to_the_last_byte() {
+            let last = (1u64 << 38) - 1;
+
+            let mut cipher = ChaCha20Impl::new(&KEY, &NONCE);
+            cipher.seek_pos(last).unwrap();
+            assert_eq!(cipher.current_pos(), last);
+
+            let mut expected = [0u8; 64];
+            let mut reference = ChaCha20Impl::new(&KEY, &NONCE);
+            reference.seek(u32::MAX);
+            reference.apply_keystream(&mut expected);
+
+            let mut buf = [0u8; 1];
+            cipher.apply_keystream(&mut buf);
+            assert_eq!(buf[0], expected[63]);
+        }
+
+        #[test]
+        fn seek_pos_round_trips_at_the_end_of_the_keystream() {
+            let mut cipher = ChaCha20Impl::new(&KEY, &NONCE);
+            cipher.seek_pos((1u64 << 38) - 10).unwrap();
+            cipher.apply_keystream(&mut [0u8; 10]);
+            let end = cipher.current_pos();
+            assert_eq!(end, 1u64 << 38);
+            assert_eq!(cipher.remaining_keystream(), 0);
+
+            let mut resumed = ChaCha20Impl::new(&KEY, &NONCE);
+            resumed.seek_pos(end).unwrap();
+            assert_eq!(resumed.current_pos(), end);
+            assert_eq!(resumed.remaining_keystream(), 0);
+            assert_eq!(resumed.try_apply_keystream(&mut [0u8; 1]), Err(Error::KeystreamExhausted));
+            assert_eq!(resumed.try_apply_keystream(&mut []), Ok(()));
+
+            // Seeking back from the end starts a fresh keystream
+            resumed.seek_pos(0).unwrap();
+            assert_eq!(resumed.remaining_keystream(), 1u64 << 38);
+        }
+
+        #[test]
+        fn seek_pos_out_of_range() {
+            let mut cipher = ChaCha20Impl::new(&KEY, &NONCE);
+            cipher.seek_pos(1000).unwrap();
+
+            assert_eq!(cipher.seek_pos((1u64 << 38) + 1), Err(Error::PositionOutOfRange));
+            assert_eq!(cipher.seek_pos(u64::MAX), Err(Error::PositionOutOfRange));
+
+            // A failed seek leaves the position unchanged
+            assert_eq!(cipher.current_pos(), 1000);
+        }
+    }
+
+    mod chacha20poly1305_tests {
+        use chacha20imp::chacha20poly1305::{ChaCha20Poly1305, Error};
+        use hex_literal::hex;
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        use {
+            chacha20imp::chacha20poly1305::{XChaCha20Poly1305, MAX_MESSAGE_SIZE},
+            chacha20imp::stream::{StreamDecryptor, StreamEncryptor},
+            std::panic::{self, AssertUnwindSafe},
+        };
+
+        // RFC 8439 section 2.8.2
+        const KEY: [u8; 32] = hex!(
+            "
+                808182838485868788898a8b8c8d8e8f
+                909192939495969798999a9b9c9d9e9f
+                "
+        );
+
+        const NONCE: [u8; 12] = hex!("070000004041424344454647");
+
+        const AAD: [u8; 12] = hex!("50515253c0c1c2c3c4c5c6c7");
+
+        const PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: \
+            If I could offer you only one tip for the future, sunscreen would be it.";
+
+        const CIPHERTEXT: [u8; 114] = hex!(
+            "
+                d31a8d34648e60db7b86afbc53ef7ec2
+                a4aded51296e08fea9e2b5a736ee62d6
+                3dbea45e8ca9671282fafb69da92728b
+                1a71de0a9e060b2905d6a5b67ecd3b36
+                92ddbd7f2d778b8c9803aee328091b58
+                fab324e4fad675945585808b4831d7bc
+                3ff4def08e4b7a9de576d26586cec64b
+                6116
+                "
+        );
+
+        const TAG: [u8; 16] = hex!("1ae10b594f09e26a7e902ecbd0600691");
+
+        #[test]
+        #[cfg(feature = "alloc")]
+        fn rfc_8439_encrypt() {
+            let aead = ChaCha20Poly1305::new(&KEY);
+            let sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+
+            assert_eq!(&sealed[..114], &CIPHERTEXT[..]);
+            assert_eq!(&sealed[114..], &TAG[..]);
+        }
+
+        #[test]
+        #[cfg(feature = "alloc")]
+        fn rfc_8439_decrypt() {
+            let mut sealed = CIPHERTEXT.to_vec();
+            sealed.extend_from_slice(&TAG);
+
+            let aead = ChaCha20Poly1305::new(&KEY);
+            let opened = aead.decrypt(&NONCE, &AAD, &sealed).unwrap();
+            assert_eq!(&opened[..], PLAINTEXT);
+        }
+
+        #[test]
+        fn rfc_8439_detached() {
+            let aead = ChaCha20Poly1305::new(&KEY);
+
+            let mut buf = PLAINTEXT.to_vec();
+            let tag = aead.encrypt_in_place_detached(&NONCE, &AAD, &mut buf);
+            assert_eq!(&buf[..], &CIPHERTEXT[..]);
+            assert_eq!(tag, TAG);
+
+            aead.decrypt_in_place_detached(&NONCE, &AAD, &mut buf, &tag).unwrap();
+            assert_eq!(&buf[..], PLAINTEXT);
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a5_decrypt() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    1c9240a5eb55d38af333888604f6b5f0
+                    473917c1402b80099dca5cbc207075c0
+                    "
+            );
+
+            const NONCE: [u8; 12] = hex!("000000000102030405060708");
+
+            const AAD: [u8; 12] = hex!("f33388860000000000004e91");
+
+            const CIPHERTEXT: [u8; 265] = hex!(
+                "
+                    64a0861575861af460f062c79be643bd
+                    5e805cfd345cf389f108670ac76c8cb2
+                    4c6cfc18755d43eea09ee94e382d26b0
+                    bdb7b73c321b0100d4f03b7f355894cf
+                    332f830e710b97ce98c8a84abd0b9481
+                    14ad176e008d33bd60f982b1ff37c855
+                    9797a06ef4f0ef61c186324e2b350638
+                    3606907b6a7c02b0f9f6157b53c867e4
+                    b9166c767b804d46a59b5216cde7a4e9
+                    9040c5a40433225ee282a1b0a06c523e
+                    af4534d7f83fa1155b0047718cbc546a
+                    0d072b04b3564eea1b422273f548271a
+                    0bb2316053fa76991955ebd63159434e
+                    cebb4e466dae5a1073a6727627097a10
+                    49e617d91d361094fa68f0ff77987130
+                    305beaba2eda04df997b714d6c6f2c29
+                    a6ad5cb4022b02709b
+                    "
+            );
+
+            const TAG: [u8; 16] = hex!("eead9d67890cbb22392336fea1851f38");
+
+            const PLAINTEXT: [u8; 265] = hex!(
+                "
+                    496e7465726e65742d44726166747320
+                    61726520647261667420646f63756d65
+                    6e74732076616c696420666f72206120
+                    6d6178696d756d206f6620736978206d
+                    6f6e74687320616e64206d6179206265
+                    20757064617465642c207265706c6163
+                    65642c206f72206f62736f6c65746564
+                    206279206f7468657220646f63756d65
+                    6e747320617420616e792074696d652e
+                    20497420697320696e617070726f7072
+                    6961746520746f2075736520496e7465
+                    726e65742d4472616674732061732072
+                    65666572656e6365206d617465726961
+                    6c206f7220746f206369746520746865
+                    6d206f74686572207468616e20617320
+                    2fe2809c776f726b20696e2070726f67
+                    726573732e2fe2809d
+                    "
+            );
+
+            let aead = ChaCha20Poly1305::new(&KEY);
+
+            let mut buf = CIPHERTEXT;
+            aead.decrypt_in_place_detached(&NONCE, &AAD, &mut buf, &TAG).unwrap();
+            assert_eq!(&buf[..], &PLAINTEXT[..]);
+
+            let mut sealed = PLAINTEXT.to_vec();
+            let tag = aead.encrypt_in_place_detached(&NONCE, &AAD, &mut sealed);
+            assert_eq!(&sealed[..], &CIPHERTEXT[..]);
+            assert_eq!(tag, TAG);
+        }
+
+        #[test]
+        #[cfg(feature = "alloc")]
+        fn rejects_modified_input() {
+            let aead = ChaCha20Poly1305::new(&KEY);
+            let sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+
+            for i in 0..sealed.len() {
+                let mut modified = sealed.clone();
+                modified[i] ^= 0x01;
+                assert_eq!(aead.decrypt(&NONCE, &AAD, &modified), Err(Error::InvalidTag));
+            }
+
+            let mut aad = AAD;
+            aad[0] ^= 0x01;
+            assert_eq!(aead.decrypt(&NONCE, &aad, &sealed), Err(Error::InvalidTag));
+
+            let mut nonce = NONCE;
+            nonce[11] ^= 0x01;
+            assert_eq!(aead.decrypt(&nonce, &AAD, &sealed), Err(Error::InvalidTag));
+        }
+
+        #[test]
+        fn failed_decryption_leaves_buffer_untouched() {
+            let aead = ChaCha20Poly1305::new(&KEY);
+
+            let mut buf = CIPHERTEXT;
+            let mut tag = TAG;
+            tag[15] ^= 0x80;
+
+            assert_eq!(
+                aead.decrypt_in_place_detached(&NONCE, &AAD, &mut buf, &tag),
+                Err(Error::InvalidTag)
+            );
+            assert_eq!(buf, CIPHERTEXT);
+        }
+
+        #[test]
+        #[cfg(feature = "alloc")]
+        fn rejects_truncated_ciphertext() {
+            let aead = ChaCha20Poly1305::new(&KEY);
+            let sealed = aead.encrypt(&NONCE, &AAD, b"");
+            assert_eq!(sealed.len(), 16);
+
+            assert!(aead.decrypt(&NONCE, &AAD, &sealed).unwrap().is_empty());
+            assert_eq!(aead.decrypt(&NONCE, &AAD, &sealed[..15]), Err(Error::InvalidTag));
+            assert_eq!(aead.decrypt(&NONCE, &AAD, &[]), Err(Error::InvalidTag));
+        }
+
+        /// Address space for `len` bytes that is only backed by memory where it is touched
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        struct Reserved {
+            ptr: *mut u8,
+            len: usize,
+        }
+
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        impl Reserved {
+            fn new(len: usize) -> Self {
+                let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE;
+                // SAFETY: a new anonymous mapping that aliases nothing
+                let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, flags, -1, 0) };
+                assert_ne!(ptr, libc::MAP_FAILED);
+                Reserved { ptr: ptr.cast(), len }
+            }
+
+            fn as_mut_slice(&mut self) -> &mut [u8] {
+                // SAFETY: the mapping is readable, writable and zero-filled, and lives as long as `self`
+                unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+            }
+        }
+
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        impl Drop for Reserved {
+            fn drop(&mut self) {
+                // SAFETY: unmaps the mapping created in `new`, which is no longer borrowed
+                unsafe { libc::munmap(self.ptr.cast(), self.len) };
+            }
+        }
+
+        /// One byte more would wrap the block counter to block 0 and reuse the Poly1305 key as
+        /// keystream. Each call has to fail before touching the buffer, which would otherwise
+        /// commit 256 GiB of memory.
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        #[test]
+        fn messages_longer_than_the_block_counter_are_rejected() {
+            assert_eq!(MAX_MESSAGE_SIZE, ((1u64 << 32) - 1) * 64);
+            let mut reserved = Reserved::new(MAX_MESSAGE_SIZE as usize + 1);
+            let buffer = reserved.as_mut_slice();
+            let aead = ChaCha20Poly1305::new(&KEY);
+            let xaead = XChaCha20Poly1305::new(&KEY);
+
+            assert_eq!(aead.decrypt_in_place_detached(&NONCE, &AAD, buffer, &[0; 16]), Err(Error::MessageTooLong));
+            assert_eq!(xaead.decrypt_in_place_detached(&[0; 24], &AAD, buffer, &[0; 16]), Err(Error::MessageTooLong));
+            assert!(StreamDecryptor::new(&KEY, &[0; 7]).decrypt_next_in_place(&AAD, buffer, &[0; 16]).is_err());
+
+            assert!(panic::catch_unwind(AssertUnwindSafe(|| aead.encrypt_in_place_detached(&NONCE, &AAD, buffer))).is_err());
+            assert!(panic::catch_unwind(AssertUnwindSafe(|| xaead.encrypt_in_place_detached(&[0; 24], &AAD, buffer))).is_err());
+            let mut encryptor = StreamEncryptor::new(&KEY, &[0; 7]);
+            assert!(panic::catch_unwind(AssertUnwindSafe(|| encryptor.encrypt_next_in_place(&AAD, buffer))).is_err());
+        }
+    }
+
+    mod poly1305_tests {
+        use chacha20imp::poly1305::{poly1305_key_gen, Error, Poly1305};
+        use hex_literal::hex;
+
+        const IETF_TEXT: &[u8] = b"Any submission to the IETF intended by the Contributor for \
+            publication as all or part of an IETF Internet-Draft or RFC and any statement made \
+            within the context of an IETF activity is considered an \"IETF Contribution\". Such \
+            statements include oral statements in IETF sessions, as well as written and \
+            electronic communications made at any time or place, which are addressed to";
+
+        const JABBERWOCKY: &[u8] = b"'Twas brillig, and the slithy toves\n\
+            Did gyre and gimble in the wabe:\n\
+            All mimsy were the borogoves,\n\
+            And the mome raths outgrabe.";
+
+        fn mac(key: &[u8; 32], msg: &[u8]) -> [u8; 16] {
+            let mut mac = Poly1305::new(key);
+            mac.update(msg);
+            mac.finalize()
+        }
+
+        #[test]
+        fn rfc_8439_section_2_5_2() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    85d6be7857556d337f4452fe42d506a8
+                    0103808afb0db2fd4abff6af4149f51b
+                    "
+            );
+
+            const TAG: [u8; 16] = hex!("a8061dc1305136c6c22b8baf0c0127a9");
+
+            assert_eq!(mac(&KEY, b"Cryptographic Forum Research Group"), TAG);
+        }
+
+        #[test]
+        fn rfc_8439_section_2_6_2_key_generation() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    808182838485868788898a8b8c8d8e8f
+                    909192939495969798999a9b9c9d9e9f
+                    "
+            );
+
+            const NONCE: [u8; 12] = hex!("000000000001020304050607");
+
+            const MAC_KEY: [u8; 32] = hex!(
+                "
+                    8ad5a08b905f81cc815040274ab29471
+                    a833b637e3fd0da508dbb8e2fdd1a646
+                    "
+            );
+
+            assert_eq!(poly1305_key_gen(&KEY, &NONCE), MAC_KEY);
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_1() {
+            assert_eq!(mac(&[0u8; 32], &[0u8; 64]), [0u8; 16]);
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_2() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    00000000000000000000000000000000
+                    36e5f6b5c5e06070f0efca96227a863e
+                    "
+            );
+
+            assert_eq!(mac(&KEY, IETF_TEXT), hex!("36e5f6b5c5e06070f0efca96227a863e"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_3() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    36e5f6b5c5e06070f0efca96227a863e
+                    00000000000000000000000000000000
+                    "
+            );
+
+            assert_eq!(mac(&KEY, IETF_TEXT), hex!("f3477e7cd95417af89a6b8794c310cf0"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_4() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    1c9240a5eb55d38af333888604f6b5f0
+                    473917c1402b80099dca5cbc207075c0
+                    "
+            );
+
+            assert_eq!(mac(&KEY, JABBERWOCKY), hex!("4541669a7eaaee61e708dc7cbcc5eb62"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_5() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    02000000000000000000000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            const MSG: [u8; 16] = hex!("ffffffffffffffffffffffffffffffff");
+
+            assert_eq!(mac(&KEY, &MSG), hex!("03000000000000000000000000000000"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_6() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    02000000000000000000000000000000
+                    ffffffffffffffffffffffffffffffff
+                    "
+            );
+
+            const MSG: [u8; 16] = hex!("02000000000000000000000000000000");
+
+            assert_eq!(mac(&KEY, &MSG), hex!("03000000000000000000000000000000"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_7() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    01000000000000000000000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            const MSG: [u8; 48] = hex!(
+                "
+                    ffffffffffffffffffffffffffffffff
+                    f0ffffffffffffffffffffffffffffff
+                    11000000000000000000000000000000
+                    "
+            );
+
+            assert_eq!(mac(&KEY, &MSG), hex!("05000000000000000000000000000000"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_8() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    01000000000000000000000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            const MSG: [u8; 48] = hex!(
+                "
+                    ffffffffffffffffffffffffffffffff
+                    fbfefefefefefefefefefefefefefefe
+                    01010101010101010101010101010101
+                    "
+            );
+
+            assert_eq!(mac(&KEY, &MSG), hex!("00000000000000000000000000000000"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_9() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    02000000000000000000000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            const MSG: [u8; 16] = hex!("fdffffffffffffffffffffffffffffff");
+
+            assert_eq!(mac(&KEY, &MSG), hex!("faffffffffffffffffffffffffffffff"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_10() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    01000000000000000400000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            const MSG: [u8; 64] = hex!(
+                "
+                    e33594d7505e43b90000000000000000
+                    3394d7505e4379cd0100000000000000
+                    00000000000000000000000000000000
+                    01000000000000000000000000000000
+                    "
+            );
+
+            assert_eq!(mac(&KEY, &MSG), hex!("14000000000000005500000000000000"));
+        }
+
+        #[test]
+        fn rfc_8439_appendix_a3_vector_11() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    01000000000000000400000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            const MSG: [u8; 48] = hex!(
+                "
+                    e33594d7505e43b90000000000000000
+                    3394d7505e4379cd0100000000000000
+                    00000000000000000000000000000000
+                    "
+            );
+
+            assert_eq!(mac(&KEY, &MSG), hex!("13000000000000000000000000000000"));
+        }
+
+        #[test]
+        fn incremental_update_matches_one_shot() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    1c9240a5eb55d38af333888604f6b5f0
+                    473917c1402b80099dca5cbc207075c0
+                    "
+            );
+
+            let expected = mac(&KEY, IETF_TEXT);
+
+            for chunk_size in 1..=IETF_TEXT.len() {
+                let mut mac = Poly1305::new(&KEY);
+                for chunk in IETF_TEXT.chunks(chunk_size) {
+                    mac.update(chunk);
+                }
+                assert_eq!(mac.finalize(), expected, "chunk size {}", chunk_size);
+            }
+        }
+
+        #[test]
+        fn verify() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    1c9240a5eb55d38af333888604f6b5f0
+                    473917c1402b80099dca5cbc207075c0
+                    "
+            );
+
+            const TAG: [u8; 16] = hex!("4541669a7eaaee61e708dc7cbcc5eb62");
+
+            let mut mac = Poly1305::new(&KEY);
+            mac.update(JABBERWOCKY);
+            assert_eq!(mac.verify(&TAG), Ok(()));
+
+            for i in 0..16 {
+                let mut tag = TAG;
+                tag[i] ^= 0x01;
+
+                let mut mac = Poly1305::new(&KEY);
+                mac.update(JABBERWOCKY);
+                assert_eq!(mac.verify(&tag), Err(Error::InvalidTag));
+            }
+        }
+    }
+
+    mod xchacha20_tests {
+        use chacha20imp::chacha20::ChaCha20;
+        use chacha20imp::xchacha20::{hchacha20, XChaCha20};
+        use hex_literal::hex;
+
+        // draft-irtf-cfrg-xchacha section 2.2.1
+        #[test]
+        fn hchacha20_test_vector() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    000102030405060708090a0b0c0d0e0f
+                    101112131415161718191a1b1c1d1e1f
+                    "
+            );
+
+            const INPUT: [u8; 16] = hex!("000000090000004a0000000031415927");
+
+            const SUBKEY: [u8; 32] = hex!(
+                "
+                    82413b4227b27bfed30e42508a877d73
+                    a0f9e4d58a74a853c12ec41326d3ecdc
+                    "
+            );
+
+            assert_eq!(hchacha20(&KEY, &INPUT), SUBKEY);
+        }
+
+        // draft-irtf-cfrg-xchacha appendix A.3.2
+        const KEY: [u8; 32] = hex!(
+            "
+                808182838485868788898a8b8c8d8e8f
+                909192939495969798999a9b9c9d9e9f
+                "
+        );
+
+        const NONCE: [u8; 24] = hex!("404142434445464748494a4b4c4d4e4f5051525354555658");
+
+        const PLAINTEXT: [u8; 304] = hex!(
+            "
+                5468652064686f6c65202870726f6e6f756e6365642022646f6c652229206973
+                20616c736f206b6e6f776e2061732074686520417369617469632077696c6420
+                646f672c2072656420646f672c20616e642077686973746c696e6720646f672e
+                2049742069732061626f7574207468652073697a65206f662061204765726d61
+                6e20736865706865726420627574206c6f6f6b73206d6f7265206c696b652061
+                206c6f6e672d6c656767656420666f782e205468697320686967686c7920656c
+                757369766520616e6420736b696c6c6564206a756d70657220697320636c6173
+                736966696564207769746820776f6c7665732c20636f796f7465732c206a6163
+                6b616c732c20616e6420666f78657320696e20746865207461786f6e6f6d6963
+                2066616d696c792043616e696461652e
+                "
+        );
+
+        const KEYSTREAM: [u8; 304] = hex!(
+            "
+                29624b4b1b140ace53740e405b2168540fd7d630c1f536fecd722fc3cddba7f4
+                cca98cf9e47e5e64d115450f9b125b54449ff76141ca620a1f9cfcab2a1a8a25
+                5e766a5266b878846120ea64ad99aa479471e63befcbd37cd1c22a221fe46221
+                5cf32c74895bf505863ccddd48f62916dc6521f1ec50a5ae08903aa259d9bf60
+                7cd8026fba548604f1b6072d91bc91243a5b845f7fd171b02edc5a0a84cf28dd
+                241146bc376e3f48df5e7fee1d11048c190a3d3deb0feb64b42d9c6fdeee290f
+                a0e6ae2c26c0249ea8c181f7e2ffd100cbe5fd3c4f8271d62b15330cb8fdcf00
+                b3df507ca8c924f7017b7e712d15a2eb5c50484451e54e1b4b995bd8fdd94597
+                bb94d7af0b2c04df10ba0890899ed9293a0f55b8bafa999264035f1d4fbe7fe0
+                aafa109a62372027e50e10cdfecca127
+                "
+        );
+
+        const CIPHERTEXT: [u8; 304] = hex!(
+            "
+                7d0a2e6b7f7c65a236542630294e063b7ab9b555a5d5149aa21e4ae1e4fbce87
+                ecc8e08a8b5e350abe622b2ffa617b202cfad72032a3037e76ffdcdc4376ee05
+                3a190d7e46ca1de04144850381b9cb29f051915386b8a710b8ac4d027b8b050f
+                7cba5854e028d564e453b8a968824173fc16488b8970cac828f11ae53cabd201
+                12f87107df24ee6183d2274fe4c8b1485534ef2c5fbc1ec24bfc3663efaa08bc
+                047d29d25043532db8391a8a3d776bf4372a6955827ccb0cdd4af403a7ce4c63
+                d595c75a43e045f0cce1f29c8b93bd65afc5974922f214a40b7c402cdb91ae73
+                c0b63615cdad0480680f16515a7ace9d39236464328a37743ffc28f4ddb324f4
+                d0f5bbdc270c65b1749a6efff1fbaa09536175ccd29fb9e6057b307320d31683
+                8a9c71f70b5b5907a66f7ea49aadc409
+                "
+        );
+
+        #[test]
+        fn xchacha20_keystream() {
+            let mut cipher = XChaCha20::new(&KEY, &NONCE);
+
+            // The test vectors start at block counter 1
+            cipher.seek(1);
+
+            let mut buf = [0u8; 304];
+            cipher.apply_keystream(&mut buf);
+            assert_eq!(&buf[..], &KEYSTREAM[..]);
+        }
+
+        #[test]
+        fn xchacha20_encryption() {
+            let mut cipher = XChaCha20::new(&KEY, &NONCE);
+            let mut buf = PLAINTEXT;
+
+            // The test vectors omit the first 64 bytes of the keystream
+            let mut prefix = [0u8; 64];
+            cipher.apply_keystream(&mut prefix);
+
+            cipher.apply_keystream(&mut buf);
+            assert_eq!(&buf[..], &CIPHERTEXT[..]);
+        }
+
+        #[test]
+        fn xchacha20_seek_pos() {
+            let mut cipher = XChaCha20::new(&KEY, &NONCE);
+            cipher.seek_pos(64 + 100).unwrap();
+            assert_eq!(cipher.current_pos(), 164);
+
+            let mut buf = [0u8; 204];
+            cipher.apply_keystream(&mut buf);
+            assert_eq!(&buf[..], &KEYSTREAM[100..]);
+        }
+
+        #[test]
+        #[should_panic(expected = "Nonce must be 192 bits")]
+        fn xchacha20_rejects_short_nonce() {
+            XChaCha20::new(&KEY, &[0u8; 12]);
+        }
+    }
+
+    mod xchacha20poly1305_tests {
+        #[cfg(feature = "alloc")]
+        use chacha20imp::chacha20poly1305::Error;
+        use chacha20imp::chacha20poly1305::XChaCha20Poly1305;
+        use hex_literal::hex;
+
+        // draft-irtf-cfrg-xchacha appendix A.3.1
+        const KEY: [u8; 32] = hex!(
+            "
+                808182838485868788898a8b8c8d8e8f
+                909192939495969798999a9b9c9d9e9f
+                "
+        );
+
+        const NONCE: [u8; 24] = hex!("404142434445464748494a4b4c4d4e4f5051525354555657");
+
+        const AAD: [u8; 12] = hex!("50515253c0c1c2c3c4c5c6c7");
+
+        const PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: \
+            If I could offer you only one tip for the future, sunscreen would be it.";
+
+        const CIPHERTEXT: [u8; 114] = hex!(
+            "
+                bd6d179d3e83d43b9576579493c0e939
+                572a1700252bfaccbed2902c21396cbb
+                731c7f1b0b4aa6440bf3a82f4eda7e39
+                ae64c6708c54c216cb96b72e1213b452
+                2f8c9ba40db5d945b11b69b982c1bb9e
+                3f3fac2bc369488f76b2383565d3fff9
+                21f9664c97637da9768812f615c68b13
+                b52e
+                "
+        );
+
+        const TAG: [u8; 16] = hex!("c0875924c1c7987947deafd8780acf49");
+
+        #[test]
+        #[cfg(feature = "alloc")]
+        fn attached_tag() {
+            let aead = XChaCha20Poly1305::new(&KEY);
+
+            let sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+            assert_eq!(&sealed[..114], &CIPHERTEXT[..]);
+            assert_eq!(&sealed[114..], &TAG[..]);
+
+            let opened = aead.decrypt(&NONCE, &AAD, &sealed).unwrap();
+            assert_eq!(&opened[..], PLAINTEXT);
+        }
+
+        #[test]
+        fn detached_tag() {
+            let aead = XChaCha20Poly1305::new(&KEY);
+
+            let mut buf = PLAINTEXT.to_vec();
+            let tag = aead.encrypt_in_place_detached(&NONCE, &AAD, &mut buf);
+            assert_eq!(&buf[..], &CIPHERTEXT[..]);
+            assert_eq!(tag, TAG);
+
+            aead.decrypt_in_place_detached(&NONCE, &AAD, &mut buf, &tag).unwrap();
+            assert_eq!(&buf[..], PLAINTEXT);
+        }
+
+        #[test]
+        #[cfg(feature = "alloc")]
+        fn rejects_modified_input() {
+            let aead = XChaCha20Poly1305::new(&KEY);
+            let sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+
+            for i in 0..sealed.len() {
+                let mut modified = sealed.clone();
+                modified[i] ^= 0x01;
+                assert_eq!(aead.decrypt(&NONCE, &AAD, &modified), Err(Error::InvalidTag));
+            }
+
+            // Both halves of the nonce are bound to the tag
+            for i in [0, 15, 16, 23] {
+                let mut nonce = NONCE;
+                nonce[i] ^= 0x01;
+                assert_eq!(aead.decrypt(&nonce, &AAD, &sealed), Err(Error::InvalidTag));
+            }
+
+            let mut buf = CIPHERTEXT;
+            assert_eq!(
+                aead.decrypt_in_place_detached(&NONCE, b"", &mut buf, &TAG),
+                Err(Error::InvalidTag)
+            );
+            assert_eq!(buf, CIPHERTEXT);
+        }
+    }
+
+    mod chacha20legacy_tests {
+        use chacha20imp::chacha20::ChaCha20;
+        use chacha20imp::chacha20legacy::ChaCha20Legacy;
+        use hex_literal::hex;
+
+        // draft-agl-tls-chacha20poly1305 section 7 and draft-strombergson-chacha-test-vectors
+        fn keystream(key: &[u8; 32], nonce: &[u8; 8], len: usize) -> Vec<u8> {
+            let mut cipher = ChaCha20Legacy::new(key, nonce);
+            let mut buf = vec![0u8; len];
+            cipher.apply_keystream(&mut buf);
+            buf
+        }
+
+        #[test]
+        fn legacy_test_vector_0() {
+            const KEYSTREAM: [u8; 128] = hex!(
+                "
+                    76b8e0ada0f13d90405d6ae55386bd28
+                    bdd219b8a08ded1aa836efcc8b770dc7
+                    da41597c5157488d7724e03fb8d84a37
+                    6a43b8f41518a11cc387b669b2ee6586
+                    9f07e7be5551387a98ba977c732d080d
+                    cb0f29a048e3656912c6533e32ee7aed
+                    29b721769ce64e43d57133b074d839d5
+                    31ed1f28510afb45ace10a1f4b794d6f
+                    "
+            );
+
+            assert_eq!(&keystream(&[0u8; 32], &[0u8; 8], 128)[..], &KEYSTREAM[..]);
+        }
+
+        #[test]
+        fn legacy_test_vector_1() {
+            const KEY: [u8; 32] = hex!(
+                "
+                    00000000000000000000000000000000
+                    00000000000000000000000000000001
+                    "
+            );
+
+            const KEYSTREAM: [u8; 64] = hex!(
+                "
+                    4540f05a9f1fb296d7736e7b208e3c96
+                    eb4fe1834688d2604f450952ed432d41
+                    bbe2a0b6ea7566d2a5d1e7e20d42af2c
+                    53d792b1c43fea817e9ad275ae546963
+                    "
+            );
+
+            assert_eq!(&keystream(&KEY, &[0u8; 8], 64)[..], &KEYSTREAM[..]);
+        }
+
+        #[test]
+        fn legacy_test_vector_2() {
+            const NONCE: [u8; 8] = hex!("0000000000000001");
+
+            const KEYSTREAM: [u8; 60] = hex!(
+                "
+                    de9cba7bf3d69ef5e786dc63973f653a
+                    0b49e015adbff7134fcb7df137821031
+                    e85a050278a7084527214f73efc7fa5b
+                    5277062eb7a0433e445f41e3
+                    "
+            );
+
+            assert_eq!(&keystream(&[0u8; 32], &NONCE, 60)[..], &KEYSTREAM[..]);
+        }
+
+        #[test]
+        fn legacy_test_vector_3() {
+            const NONCE: [u8; 8] = hex!("0100000000000000");
+
+            const KEYSTREAM: [u8; 64] = hex!(
+                "
+                    ef3fdfd6c61578fbf5cf35bd3dd33b80
+                    09631634d21e42ac33960bd138e50d32
+                    111e4caf237ee53ca8ad6426194a8854
+                    5ddc497a0b466e7d6bbdb0041b2f586b
+                    "
+            );
+
+            assert_eq!(&keystream(&[0u8; 32], &NONCE, 64)[..], &KEYSTREAM[..]);
+        }
+
+        const KEY_LONG: [u8; 32] = hex!(
+            "
+                000102030405060708090a0b0c0d0e0f
+                101112131415161718191a1b1c1d1e1f
+                "
+        );
+
+        const NONCE_LONG: [u8; 8] = hex!("0001020304050607");
+
+        const KEYSTREAM_LONG: [u8; 256] = hex!(
+            "
+                f798a189f195e66982105ffb640bb775
+                7f579da31602fc93ec01ac56f85ac3c1
+                34a4547b733b46413042c94400491769
+                05d3be59ea1c53f15916155c2be8241a
+                38008b9a26bc35941e2444177c8ade66
+                89de95264986d95889fb60e84629c9bd
+                9a5acb1cc118be563eb9b3a4a472f82e
+                09a7e778492b562ef7130e88dfe031c7
+                9db9d4f7c7a899151b9a475032b63fc3
+                85245fe054e3dd5a97a5f576fe064025
+                d3ce042c566ab2c507b138db853e3d69
+                59660996546cc9c4a6eafdc777c040d7
+                0eaf46f76dad3979e5c5360c3317166a
+                1c894c94a371876a94df7628fe4eaaf2
+                ccb27d5aaae0ad7ad0f9d4b6ad3b5409
+                8746d4524d38407a6deb3ab78fab78c9
+                "
+        );
+
+        #[test]
+        fn legacy_test_vector_4() {
+            assert_eq!(&keystream(&KEY_LONG, &NONCE_LONG, 256)[..], &KEYSTREAM_LONG[..]);
+        }
+
+        #[test]
+        fn legacy_every_offset() {
+            for pos in 0..256 {
+                for split in pos..=256 {
+                    let mut cipher = ChaCha20Legacy::new(&KEY_LONG, &NONCE_LONG);
+                    cipher.seek_pos(pos as u64).unwrap();
+
+                    let mut buf = [0u8; 256];
+                    cipher.apply_keystream(&mut buf[pos..split]);
+                    cipher.apply_keystream(&mut buf[split..]);
+                    assert_eq!(&buf[pos..], &KEYSTREAM_LONG[pos..], "seek to {}, split at {}", pos, split);
+                    assert_eq!(cipher.current_pos(), 256);
+                }
+            }
+        }
+
+        #[test]
+        fn legacy_counter_carries_into_high_word() {
+            let mut cipher = ChaCha20Legacy::new(&KEY_LONG, &NONCE_LONG);
+            cipher.seek(u32::MAX);
+
+            let mut crossing = [0u8; 128];
+            cipher.apply_keystream(&mut crossing);
+            assert_eq!(cipher.current_pos(), (1u64 << 32) * 64 + 64);
+
+            let mut after = ChaCha20Legacy::new(&KEY_LONG, &NONCE_LONG);
+            after.seek_pos((1u64 << 32) * 64).unwrap();
+
+            let mut block = [0u8; 64];
+            after.apply_keystream(&mut block);
+            assert_eq!(&crossing[64..], &block[..]);
+
+            // Block 2^32 must differ from block 0, which a 32-bit counter would wrap to
+            assert_ne!(&block[..], &keystream(&KEY_LONG, &NONCE_LONG, 64)[..]);
+        }
+
+        #[test]
+        fn legacy_seek_pos_beyond_32_bit_counter() {
+            let pos = (1u64 << 50) + 17;
+
+            let mut cipher = ChaCha20Legacy::new(&KEY_LONG, &NONCE_LONG);
+            cipher.seek_pos(pos).unwrap();
+            assert_eq!(cipher.current_pos(), pos);
+
+            let mut buf = [0u8; 100];
+            cipher.apply_keystream(&mut buf);
+            assert_eq!(cipher.current_pos(), pos + 100);
+        }
+    }
+
+    mod reduced_rounds_tests {
+        use chacha20imp::chacha20::{ChaCha12Impl, ChaCha20, ChaCha20Impl, ChaCha8Impl, ChaChaImpl};
+        use hex_literal::hex;
+
+        // draft-strombergson-chacha-test-vectors. The 64-bit IV is placed in the last 8 bytes
+        // of the 96-bit nonce, which gives the same state as the original layout
+        const TC8_KEY: [u8; 32] = hex!(
+            "
+                c46ec1b18ce8a878725a37e780dfb735
+                1f68ed2e194c79fbc6aebee1a667975d
+                "
+        );
+
+        const TC8_NONCE: [u8; 12] = hex!("000000001ada31d5cf688221");
+
+        fn keystream<C: ChaCha20>(key: &[u8], nonce: &[u8]) -> [u8; 128] {
+            let mut cipher = C::new(key, nonce);
+            let mut buf = [0u8; 128];
+            cipher.apply_keystream(&mut buf);
+            buf
+        }
+
+        #[test]
+        fn chacha8_tc1() {
+            const KEYSTREAM: [u8; 128] = hex!(
+                "
+                    3e00ef2f895f40d67f5bb8e81f09a5a1
+                    2c840ec3ce9a7f3b181be188ef711a1e
+                    984ce172b9216f419f445367456d5619
+                    314a42a3da86b001387bfdb80e0cfe42
+                    d2aefa0deaa5c151bf0adb6c01f2a5ad
+                    c0fd581259f9a2aadcf20f8fd566a26b
+                    5032ec38bbc5da98ee0c6f568b872a65
+                    a08abf251deb21bb4b56e5d8821e68aa
+                    "
+            );
+
+            assert_eq!(keystream::<ChaCha8Impl>(&[0u8; 32], &[0u8; 12]), KEYSTREAM);
+        }
+
+        #[test]
+        fn chacha8_tc8() {
+            const KEYSTREAM: [u8; 128] = hex!(
+                "
+                    838751b42d8ddd8a3d77f48825a2ba75
+                    2cf4047cb308a5978ef274973be374c9
+                    6ad848065871417b08f034e681fe46a9
+                    3f7d5c61d1306614d4aaf257a7cff08b
+                    16f2fda170cc18a4b58a2667ed962774
+                    af792a6e7f3c77992540711a7a136d7e
+                    8a2f8d3f93816709d45a3fa5f8ce72fd
+                    e15be7b841acba3a2abd557228d9fe4f
+                    "
+            );
+
+            assert_eq!(keystream::<ChaCha8Impl>(&TC8_KEY, &TC8_NONCE), KEYSTREAM);
+        }
+
+        #[test]
+        fn chacha12_tc1() {
+            const KEYSTREAM: [u8; 128] = hex!(
+                "
+                    9bf49a6a0755f953811fce125f2683d5
+                    0429c3bb49e074147e0089a52eae155f
+                    0564f879d27ae3c02ce82834acfa8c79
+                    3a629f2ca0de6919610be82f411326be
+                    0bd58841203e74fe86fc71338ce0173d
+                    c628ebb719bdcbcc151585214cc089b4
+                    42258dcda14cf111c602b8971b8cc843
+                    e91e46ca905151c02744a6b017e69316
+                    "
+            );
+
+            assert_eq!(keystream::<ChaCha12Impl>(&[0u8; 32], &[0u8; 12]), KEYSTREAM);
+        }
+
+        #[test]
+        fn chacha12_tc8() {
+            const KEYSTREAM: [u8; 128] = hex!(
+                "
+                    1482072784bc6d06b4e73bdc118bc010
+                    3c7976786ca918e06986aa251f7e9cc1
+                    b2749a0a16ee83b4242d2e99b08d7c20
+                    092b80bc466c87283b61b1b39d0ffbab
+                    d94b116bc1ebdb329b9e4f620db69554
+                    4a8e3d9b68473d0c975a46ad966ed631
+                    e42aff530ad5eac7d8047adfa1e5113c
+                    91f3e3b883f1d189ac1c8fe07ba5a42b
+                    "
+            );
+
+            assert_eq!(keystream::<ChaCha12Impl>(&TC8_KEY, &TC8_NONCE), KEYSTREAM);
+        }
+
+        #[test]
+        fn chacha20_alias_is_the_20_round_variant() {
+            assert_eq!(
+                keystream::<ChaCha20Impl>(&TC8_KEY, &TC8_NONCE),
+                keystream::<ChaChaImpl<20>>(&TC8_KEY, &TC8_NONCE)
+            );
+            assert_ne!(
+                keystream::<ChaCha20Impl>(&TC8_KEY, &TC8_NONCE),
+                keystream::<ChaCha12Impl>(&TC8_KEY, &TC8_NONCE)
+            );
+        }
+    }
+
+    mod constructor_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl, Error};
+        use chacha20imp::chacha20legacy::ChaCha20Legacy;
+        use chacha20imp::xchacha20::XChaCha20;
+
+        fn keystream<C: ChaCha20>(mut cipher: C) -> [u8; 100] {
+            let mut buf = [0u8; 100];
+            cipher.apply_keystream(&mut buf);
+            buf
+        }
+
+        #[test]
+        fn try_new_reports_bad_lengths() {
+            assert_eq!(ChaCha20Impl::try_new(&[0u8; 31], &[0u8; 12]).err(), Some(Error::InvalidKeyLength));
+            assert_eq!(ChaCha20Impl::try_new(&[0u8; 33], &[0u8; 12]).err(), Some(Error::InvalidKeyLength));
+            assert_eq!(ChaCha20Impl::try_new(&[0u8; 32], &[0u8; 8]).err(), Some(Error::InvalidNonceLength));
+            assert_eq!(ChaCha20Impl::try_new(&[], &[]).err(), Some(Error::InvalidKeyLength));
+
+            assert_eq!(XChaCha20::try_new(&[0u8; 32], &[0u8; 12]).err(), Some(Error::InvalidNonceLength));
+            assert_eq!(ChaCha20Legacy::try_new(&[0u8; 32], &[0u8; 12]).err(), Some(Error::InvalidNonceLength));
+            assert_eq!(ChaCha20Legacy::try_new(&[0u8; 16], &[0u8; 8]).err(), Some(Error::InvalidKeyLength));
+        }
+
+        #[test]
+        fn try_new_matches_new() {
+            let key = [7u8; 32];
+
+            assert_eq!(
+                keystream(ChaCha20Impl::try_new(&key, &[1u8; 12]).unwrap()),
+                keystream(ChaCha20Impl::new(&key, &[1u8; 12]))
+            );
+            assert_eq!(
+                keystream(XChaCha20::try_new(&key, &[1u8; 24]).unwrap()),
+                keystream(XChaCha20::new(&key, &[1u8; 24]))
+            );
+            assert_eq!(
+                keystream(ChaCha20Legacy::try_new(&key, &[1u8; 8]).unwrap()),
+                keystream(ChaCha20Legacy::new(&key, &[1u8; 8]))
+            );
+        }
+
+        #[test]
+        fn typed_constructors_match_new() {
+            let key = [7u8; 32];
+
+            assert_eq!(
+                keystream(ChaCha20Impl::from_key_nonce(&key, &[1u8; 12])),
+                keystream(ChaCha20Impl::new(&key, &[1u8; 12]))
+            );
+            assert_eq!(
+                keystream(XChaCha20::from_key_nonce(&key, &[1u8; 24])),
+                keystream(XChaCha20::new(&key, &[1u8; 24]))
+            );
+            assert_eq!(
+                keystream(ChaCha20Legacy::from_key_nonce(&key, &[1u8; 8])),
+                keystream(ChaCha20Legacy::new(&key, &[1u8; 8]))
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Key must be 256 bits")]
+        fn new_still_panics_on_bad_key() {
+            ChaCha20Impl::new(&[0u8; 16], &[0u8; 12]);
+        }
+
+        #[test]
+        fn error_messages() {
+            assert_eq!(Error::InvalidKeyLength.to_string(), "Invalid key length");
+            assert_eq!(Error::InvalidNonceLength.to_string(), "Invalid nonce length");
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn error_is_a_std_error() {
+            let err: Box<dyn std::error::Error> = Box::new(Error::InvalidKeyLength);
+            assert_eq!(err.to_string(), "Invalid key length");
+        }
+    }
+
+    mod counter_exhaustion_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl, Error};
+        use chacha20imp::chacha20legacy::ChaCha20Legacy;
+        use chacha20imp::xchacha20::XChaCha20;
+
+        const KEYSTREAM_LEN: u64 = 1 << 38;
+
+        #[test]
+        fn remaining_keystream_tracks_position() {
+            let mut cipher = ChaCha20Impl::new(&[1u8; 32], &[2u8; 12]);
+            assert_eq!(cipher.remaining_keystream(), KEYSTREAM_LEN);
+
+            cipher.apply_keystream(&mut [0u8; 100]);
+            assert_eq!(cipher.remaining_keystream(), KEYSTREAM_LEN - 100);
+
+            cipher.seek_pos(KEYSTREAM_LEN - 10).unwrap();
+            assert_eq!(cipher.remaining_keystream(), 10);
+
+            cipher.seek(u32::MAX);
+            assert_eq!(cipher.remaining_keystream(), 64);
+        }
+
+        #[test]
+        fn try_apply_keystream_stops_at_the_end_of_the_counter() {
+            let mut cipher = ChaCha20Impl::new(&[1u8; 32], &[2u8; 12]);
+            cipher.seek(u32::MAX);
+
+            let mut expected = [0u8; 64];
+            let mut reference = ChaCha20Impl::new(&[1u8; 32], &[2u8; 12]);
+            reference.seek(u32::MAX);
+            reference.apply_keystream(&mut expected);
+
+            // Too long: nothing is processed and the position is unchanged
+            let mut buf = [0u8; 65];
+            assert_eq!(cipher.try_apply_keystream(&mut buf), Err(Error::KeystreamExhausted));
+            assert_eq!(buf, [0u8; 65]);
+            assert_eq!(cipher.current_pos(), KEYSTREAM_LEN - 64);
+
+            let mut buf = [0u8; 64];
+            cipher.try_apply_keystream(&mut buf[..30]).unwrap();
+            cipher.try_apply_keystream(&mut buf[30..]).unwrap();
+            assert_eq!(buf, expected);
+
+            assert_eq!(cipher.remaining_keystream(), 0);
+            assert_eq!(cipher.current_pos(), KEYSTREAM_LEN);
+            assert_eq!(cipher.try_apply_keystream(&mut [0u8; 1]), Err(Error::KeystreamExhausted));
+            assert_eq!(cipher.try_apply_keystream(&mut []), Ok(()));
+        }
+
+        #[test]
+        fn seek_resets_exhaustion() {
+            let mut cipher = ChaCha20Impl::new(&[1u8; 32], &[2u8; 12]);
+            cipher.seek(u32::MAX);
+            cipher.apply_keystream(&mut [0u8; 64]);
+            assert_eq!(cipher.remaining_keystream(), 0);
+
+            cipher.seek(0);
+            assert_eq!(cipher.remaining_keystream(), KEYSTREAM_LEN);
+            assert_eq!(cipher.try_apply_keystream(&mut [0u8; 64]), Ok(()));
+        }
+
+        #[test]
+        fn unchecked_apply_keystream_wraps_around() {
+            let mut cipher = ChaCha20Impl::new(&[1u8; 32], &[2u8; 12]);
+            cipher.seek(u32::MAX);
+
+            let mut buf = [0u8; 128];
+            cipher.apply_keystream(&mut buf);
+
+            let mut first = [0u8; 64];
+            ChaCha20Impl::new(&[1u8; 32], &[2u8; 12]).apply_keystream(&mut first);
+            assert_eq!(&buf[64..], &first[..]);
+        }
+
+        #[test]
+        fn other_variants() {
+            let mut xchacha = XChaCha20::new(&[1u8; 32], &[2u8; 24]);
+            xchacha.seek(u32::MAX);
+            assert_eq!(xchacha.remaining_keystream(), 64);
+            assert_eq!(xchacha.try_apply_keystream(&mut [0u8; 65]), Err(Error::KeystreamExhausted));
+
+            let mut legacy = ChaCha20Legacy::new(&[1u8; 32], &[2u8; 8]);
+            legacy.seek(u32::MAX);
+            assert_eq!(legacy.remaining_keystream(), u64::MAX);
+            assert_eq!(legacy.try_apply_keystream(&mut [0u8; 128]), Ok(()));
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    mod zeroize_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        use chacha20imp::chacha20legacy::ChaCha20Legacy;
+        use chacha20imp::chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+        use chacha20imp::poly1305::Poly1305;
+        use zeroize::{Zeroize, ZeroizeOnDrop};
+
+        /// With the offset wiped the buffered block is used first, and with every word of the
+        /// state wiped the next block is all zeros too. Applying 128 bytes of keystream is then
+        /// a no-op.
+        fn assert_wiped<C: ChaCha20>(cipher: &mut C) {
+            let mut buf = [0xaau8; 128];
+            cipher.apply_keystream(&mut buf);
+            assert_eq!(buf, [0xaau8; 128]);
+        }
+
+        #[test]
+        fn zeroize_clears_state_and_buffered_keystream() {
+            let mut cipher = ChaCha20Impl::new(&[0xffu8; 32], &[0xffu8; 12]);
+            cipher.apply_keystream(&mut [0u8; 10]);
+
+            cipher.zeroize();
+            assert_wiped(&mut cipher);
+        }
+
+        #[test]
+        fn zeroize_clears_legacy_state() {
+            let mut cipher = ChaCha20Legacy::new(&[0xffu8; 32], &[0xffu8; 8]);
+            cipher.apply_keystream(&mut [0u8; 10]);
+
+            cipher.zeroize();
+            assert_wiped(&mut cipher);
+        }
+
+        /// The Drop implementations call `zeroize`, which the other tests check on live values;
+        /// a value cannot be inspected once its Drop has run
+        #[test]
+        fn key_holders_zeroize_on_drop() {
+            fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+
+            assert_zeroize_on_drop::<ChaCha20Impl>();
+            assert_zeroize_on_drop::<ChaCha20Legacy>();
+            assert_zeroize_on_drop::<Poly1305>();
+            assert_zeroize_on_drop::<ChaCha20Poly1305>();
+            assert_zeroize_on_drop::<XChaCha20Poly1305>();
+        }
+
+        #[test]
+        fn zeroize_clears_poly1305_key() {
+            let mut mac = Poly1305::new(&[0xffu8; 32]);
+            mac.update(b"some message");
+
+            // With r, s and the accumulator wiped the tag of any message is zero
+            mac.zeroize();
+            mac.update(b"another message");
+            assert_eq!(mac.finalize(), [0u8; 16]);
+        }
+    }
+
+    mod backend_tests {
+        use chacha20imp::backends::Backend;
+        use chacha20imp::chacha20::{ChaCha12Impl, ChaCha20, ChaCha20Impl, ChaCha8Impl, ChaChaImpl};
+        use chacha20imp::xchacha20::XChaCha20;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        fn available() -> impl Iterator<Item = Backend> {
+            Backend::ALL.into_iter().filter(|backend| backend.is_available())
+        }
+
+        /// Encrypt random data with `backend` and with the scalar backend, starting at a random
+        /// counter and in two calls of random length, and compare the results
+        fn assert_matches_soft<const ROUNDS: usize>(backend: Backend, rng: &mut StdRng) {
+            let key: [u8; 32] = rng.gen();
+            let nonce: [u8; 12] = rng.gen();
+            let counter = if rng.gen_bool(0.25) { u32::MAX - rng.gen_range(0..16) } else { rng.gen() };
+            let len = rng.gen_range(0..2048);
+            let split = rng.gen_range(0..=len);
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+
+            let mut expected = data.clone();
+            let mut soft = ChaChaImpl::<ROUNDS>::from_key_nonce(&key, &nonce);
+            soft.set_backend(Backend::Soft);
+            soft.seek(counter);
+            soft.apply_keystream(&mut expected[..split]);
+            soft.apply_keystream(&mut expected[split..]);
+
+            let mut actual = data;
+            let mut cipher = ChaChaImpl::<ROUNDS>::from_key_nonce(&key, &nonce);
+            cipher.set_backend(backend);
+            cipher.seek(counter);
+            cipher.apply_keystream(&mut actual[..split]);
+            cipher.apply_keystream(&mut actual[split..]);
+
+            assert_eq!(actual, expected, "{backend:?}, {ROUNDS} rounds, counter {counter}, split {split}/{len}");
+            assert_eq!(cipher.current_pos(), soft.current_pos());
+            assert_eq!(cipher.remaining_keystream(), soft.remaining_keystream());
+        }
+
+        #[test]
+        fn detected_backend_is_available() {
+            assert!(Backend::Soft.is_available());
+            assert!(Backend::detect().is_available());
+            assert_eq!(ChaCha20Impl::new(&[0u8; 32], &[0u8; 12]).backend(), Backend::detect());
+        }
+
+        /// Detection asks the CPU at runtime in every build, including the default one without `std`
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        #[test]
+        fn detect_selects_the_backends_the_cpu_supports() {
+            assert_eq!(Backend::Sse2.is_available(), std::is_x86_feature_detected!("sse2"));
+            assert_eq!(Backend::Avx2.is_available(), std::is_x86_feature_detected!("avx2"));
+            if std::is_x86_feature_detected!("avx2") {
+                assert_eq!(Backend::detect(), Backend::Avx2);
+            }
+        }
+
+        #[test]
+        fn every_backend_matches_soft() {
+            let mut rng = StdRng::seed_from_u64(0x6368_6163_6861);
+            for backend in available() {
+                for _ in 0..200 {
+                    assert_matches_soft::<20>(backend, &mut rng);
+                }
+                for _ in 0..50 {
+                    assert_matches_soft::<8>(backend, &mut rng);
+                    assert_matches_soft::<12>(backend, &mut rng);
+                }
+            }
+        }
+
+        #[test]
+        fn every_backend_passes_rfc_8439_vector() {
+            // RFC 8439 section 2.4.2
+            let key: Vec<u8> = (0..32).collect();
+            let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+            let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+            let mut expected = *plaintext;
+            let mut soft = ChaCha20Impl::new(&key, &nonce);
+            soft.set_backend(Backend::Soft);
+            soft.seek(1);
+            soft.apply_keystream(&mut expected);
+            assert_eq!(&expected[..8], &[0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80]);
+
+            for backend in available() {
+                let mut buf = *plaintext;
+                let mut cipher = ChaCha20Impl::new(&key, &nonce);
+                cipher.set_backend(backend);
+                cipher.seek(1);
+                cipher.apply_keystream(&mut buf);
+                assert_eq!(buf, expected, "{backend:?}");
+            }
+        }
+
+        #[test]
+        fn every_backend_wraps_the_counter_like_soft() {
+            for backend in available() {
+                let mut cipher = ChaCha20Impl::new(&[7u8; 32], &[9u8; 12]);
+                cipher.set_backend(backend);
+                cipher.seek(u32::MAX - 2);
+                let mut wrapped = [0u8; 64 * 12];
+                cipher.apply_keystream(&mut wrapped);
+
+                let mut soft = ChaCha20Impl::new(&[7u8; 32], &[9u8; 12]);
+                soft.set_backend(Backend::Soft);
+                soft.seek(u32::MAX - 2);
+                let mut expected = [0u8; 64 * 12];
+                soft.apply_keystream(&mut expected);
+
+                let mut block_0 = [0u8; 64];
+                soft.seek(0);
+                soft.apply_keystream(&mut block_0);
+
+                assert_eq!(wrapped, expected, "{backend:?}");
+                assert_eq!(wrapped[64 * 3..64 * 4], block_0, "{backend:?}");
+            }
+        }
+
+        #[test]
+        fn soft_wide_path_matches_single_blocks() {
+            let mut rng = StdRng::seed_from_u64(256);
+            for _ in 0..50 {
+                let key: [u8; 32] = rng.gen();
+                let nonce: [u8; 12] = rng.gen();
+                let counter = if rng.gen_bool(0.25) { u32::MAX - rng.gen_range(0..8) } else { rng.gen() };
+                let data: Vec<u8> = (0..rng.gen_range(256..2048)).map(|_| rng.gen()).collect();
+
+                // Inputs shorter than 256 bytes never reach the wide path
+                let mut expected = data.clone();
+                let mut single = ChaCha20Impl::from_key_nonce(&key, &nonce);
+                single.set_backend(Backend::Soft);
+                single.seek(counter);
+                for byte in expected.chunks_mut(1) {
+                    single.apply_keystream(byte);
+                }
+
+                let mut actual = data;
+                let mut wide = ChaCha20Impl::from_key_nonce(&key, &nonce);
+                wide.set_backend(Backend::Soft);
+                wide.seek(counter);
+                wide.apply_keystream(&mut actual);
+
+                assert_eq!(actual, expected, "counter {counter}, length {}", actual.len());
+                assert_eq!(wide.current_pos(), single.current_pos());
+            }
+        }
+
+        #[test]
+        fn xchacha20_backends_agree() {
+            let mut rng = StdRng::seed_from_u64(24);
+            let key: [u8; 32] = rng.gen();
+            let nonce: [u8; 24] = rng.gen();
+
+            let mut expected = [0u8; 1000];
+            let mut soft = XChaCha20::from_key_nonce(&key, &nonce);
+            soft.set_backend(Backend::Soft);
+            soft.apply_keystream(&mut expected);
+
+            for backend in available() {
+                let mut buf = [0u8; 1000];
+                let mut cipher = XChaCha20::from_key_nonce(&key, &nonce);
+                cipher.set_backend(backend);
+                cipher.apply_keystream(&mut buf);
+                assert_eq!(buf, expected, "{backend:?}");
+            }
+        }
+
+        #[test]
+        fn reduced_round_aliases_use_detected_backend() {
+            assert_eq!(ChaCha8Impl::from_key_nonce(&[0u8; 32], &[0u8; 12]).backend(), Backend::detect());
+            assert_eq!(ChaCha12Impl::from_key_nonce(&[0u8; 32], &[0u8; 12]).backend(), Backend::detect());
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    mod parallel_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl, Error};
+        use chacha20imp::chacha20legacy::ChaCha20Legacy;
+        use chacha20imp::parallel::ParallelKeystream;
+        use chacha20imp::xchacha20::XChaCha20;
+
+        const LENGTHS: [usize; 9] = [0, 1, 63, 64, 65, 1000, 4097, 65_537, 300_001];
+
+        fn data(len: usize) -> Vec<u8> {
+            (0..len).map(|i| (i * 7 + 3) as u8).collect()
+        }
+
+        /// Apply `skip` bytes, then `len` bytes sequentially and in parallel, and compare the
+        /// output and the final position
+        fn assert_matches_sequential<C: ParallelKeystream>(cipher: C, skip: usize, len: usize, min_chunk_size: usize) {
+            let mut sequential = cipher.clone();
+            let mut parallel = cipher;
+            sequential.apply_keystream(&mut vec![0u8; skip]);
+            parallel.apply_keystream(&mut vec![0u8; skip]);
+
+            let mut expected = data(len);
+            sequential.apply_keystream(&mut expected);
+
+            let mut actual = data(len);
+            parallel.par_apply_keystream_with_min_chunk(&mut actual, min_chunk_size).unwrap();
+
+            assert_eq!(actual, expected, "skip {skip}, length {len}, chunk {min_chunk_size}");
+            assert_eq!(parallel.current_pos(), sequential.current_pos());
+
+            // Both ciphers continue identically
+            let mut next_sequential = [0u8; 100];
+            let mut next_parallel = [0u8; 100];
+            sequential.apply_keystream(&mut next_sequential);
+            parallel.apply_keystream(&mut next_parallel);
+            assert_eq!(next_parallel, next_sequential);
+        }
+
+        #[test]
+        fn matches_sequential_for_odd_lengths() {
+            let cipher = ChaCha20Impl::new(&[0x11u8; 32], &[0x22u8; 12]);
+            for len in LENGTHS {
+                for skip in [0, 1, 63, 64, 100] {
+                    for min_chunk_size in [1, 64, 100, 4096] {
+                        assert_matches_sequential(cipher.clone(), skip, len, min_chunk_size);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn default_chunk_size() {
+            let mut sequential = ChaCha20Impl::new(&[0x33u8; 32], &[0x44u8; 12]);
+            let mut parallel = sequential.clone();
+
+            let mut expected = data(1_000_003);
+            sequential.apply_keystream(&mut expected);
+            let mut actual = data(1_000_003);
+            parallel.par_apply_keystream(&mut actual).unwrap();
+
+            assert_eq!(actual, expected);
+            assert_eq!(parallel.current_pos(), 1_000_003);
+        }
+
+        #[test]
+        fn xchacha20_and_legacy_match_sequential() {
+            let xchacha = XChaCha20::new(&[0x55u8; 32], &[0x66u8; 24]);
+            let legacy = ChaCha20Legacy::new(&[0x77u8; 32], &[0x88u8; 8]);
+            for len in LENGTHS {
+                assert_matches_sequential(xchacha.clone(), 17, len, 1000);
+                assert_matches_sequential(legacy.clone(), 17, len, 1000);
+            }
+        }
+
+        #[test]
+        fn legacy_crosses_32_bit_counter() {
+            let mut legacy = ChaCha20Legacy::new(&[0x99u8; 32], &[0xaau8; 8]);
+            legacy.seek_pos((u32::MAX as u64 - 10) * 64 + 5).unwrap();
+            assert_matches_sequential(legacy, 0, 5000, 256);
+        }
+
+        #[test]
+        fn runs_up_to_the_end_of_the_keystream() {
+            let mut cipher = ChaCha20Impl::new(&[0xbbu8; 32], &[0xccu8; 12]);
+            cipher.seek(u32::MAX - 15);
+            cipher.apply_keystream(&mut [0u8; 3]);
+            let len = cipher.remaining_keystream() as usize;
+
+            let mut sequential = cipher.clone();
+            let mut expected = data(len);
+            sequential.try_apply_keystream(&mut expected).unwrap();
+
+            let mut actual = data(len);
+            cipher.par_apply_keystream_with_min_chunk(&mut actual, 128).unwrap();
+
+            assert_eq!(actual, expected);
+            assert_eq!(cipher.remaining_keystream(), 0);
+            assert_eq!(cipher.par_apply_keystream(&mut [0u8; 1]), Err(Error::KeystreamExhausted));
+        }
+
+        #[test]
+        fn rejects_input_past_the_end_without_touching_it() {
+            let mut cipher = ChaCha20Impl::new(&[0xddu8; 32], &[0xeeu8; 12]);
+            cipher.seek(u32::MAX - 1);
+
+            let mut buf = data(129);
+            assert_eq!(cipher.par_apply_keystream_with_min_chunk(&mut buf, 64), Err(Error::KeystreamExhausted));
+            assert_eq!(buf, data(129));
+            assert_eq!(cipher.current_pos(), (u32::MAX as u64 - 1) * 64);
+        }
+    }
+
+    #[cfg(feature = "cipher")]
+    mod cipher_tests {
+        use chacha20imp::chacha20::{self, ChaCha20Impl};
+        use chacha20imp::chacha20legacy::ChaCha20Legacy;
+        use chacha20imp::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+        use chacha20imp::xchacha20::XChaCha20;
+        use hex_literal::hex;
+
+        // RFC 8439 section 2.4.2
+        const KEY: [u8; 32] = hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        const NONCE: [u8; 12] = hex!("000000000000004a00000000");
+        const PLAINTEXT: &[u8; 114] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        const CIPHERTEXT: [u8; 114] = hex!(
+            "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b"
+            "f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8"
+            "07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736"
+            "5af90bbf74a35be6b40b8eedf2785e42874d"
+        );
+
+        /// Encrypt with a cipher that is only known through the RustCrypto traits
+        fn encrypt_generic<C: KeyIvInit + StreamCipher + StreamCipherSeek>(key: &[u8], iv: &[u8], pos: u64, data: &mut [u8]) {
+            let mut cipher = C::new_from_slices(key, iv).unwrap();
+            cipher.seek(pos);
+            cipher.apply_keystream(data);
+        }
+
+        #[test]
+        fn rfc_8439_through_the_traits() {
+            let mut buf = *PLAINTEXT;
+            encrypt_generic::<ChaCha20Impl>(&KEY, &NONCE, 64, &mut buf);
+            assert_eq!(buf, CIPHERTEXT);
+
+            encrypt_generic::<ChaCha20Impl>(&KEY, &NONCE, 64, &mut buf);
+            assert_eq!(&buf, PLAINTEXT);
+        }
+
+        #[test]
+        fn buffer_to_buffer() {
+            let mut cipher = <ChaCha20Impl as KeyIvInit>::new(&KEY.into(), &NONCE.into());
+            cipher.seek(64u32);
+
+            let mut output = [0u8; 114];
+            cipher.apply_keystream_b2b(PLAINTEXT, &mut output).unwrap();
+            assert_eq!(output, CIPHERTEXT);
+            assert!(cipher.apply_keystream_b2b(PLAINTEXT, &mut [0u8; 10]).is_err());
+        }
+
+        #[test]
+        fn new_from_slices_checks_lengths() {
+            assert!(ChaCha20Impl::new_from_slices(&KEY[..16], &NONCE).is_err());
+            assert!(ChaCha20Impl::new_from_slices(&KEY, &NONCE[..8]).is_err());
+            assert!(XChaCha20::new_from_slices(&KEY, &NONCE).is_err());
+            assert!(ChaCha20Legacy::new_from_slices(&KEY, &NONCE[..8]).is_ok());
+        }
+
+        #[test]
+        fn matches_the_crate_api() {
+            let mut expected = [0u8; 1000];
+            chacha20::ChaCha20::apply_keystream(&mut <XChaCha20 as chacha20::ChaCha20>::new(&KEY, &[7u8; 24]), &mut expected);
+            let mut buf = [0u8; 1000];
+            encrypt_generic::<XChaCha20>(&KEY, &[7u8; 24], 0, &mut buf);
+            assert_eq!(buf, expected);
+
+            let mut expected = [0u8; 1000];
+            let mut legacy = <ChaCha20Legacy as chacha20::ChaCha20>::new(&KEY, &[9u8; 8]);
+            chacha20::ChaCha20::seek_pos(&mut legacy, 1 << 40).unwrap();
+            chacha20::ChaCha20::apply_keystream(&mut legacy, &mut expected);
+            let mut buf = [0u8; 1000];
+            encrypt_generic::<ChaCha20Legacy>(&KEY, &[9u8; 8], 1 << 40, &mut buf);
+            assert_eq!(buf, expected);
+        }
+
+        #[test]
+        fn positions_round_trip() {
+            let mut cipher = <ChaCha20Impl as KeyIvInit>::new(&KEY.into(), &NONCE.into());
+            for pos in [0u64, 1, 63, 64, 65, 1000, (u32::MAX as u64) * 64 + 63] {
+                cipher.try_seek(pos).unwrap();
+                assert_eq!(cipher.try_current_pos::<u64>().unwrap(), pos);
+                assert_eq!(chacha20::ChaCha20::current_pos(&cipher), pos);
+            }
+
+            cipher.seek(0u32);
+            cipher.apply_keystream(&mut [0u8; 70]);
+            assert_eq!(cipher.current_pos::<u32>(), 70);
+            assert_eq!(cipher.current_pos::<u128>(), 70);
+        }
+
+        #[test]
+        fn current_pos_overflow() {
+            let mut cipher = <ChaCha20Impl as KeyIvInit>::new(&KEY.into(), &NONCE.into());
+            cipher.seek(1u64 << 32);
+            assert!(cipher.try_current_pos::<u32>().is_err());
+            assert_eq!(cipher.try_current_pos::<u64>().unwrap(), 1 << 32);
+
+            cipher.seek(u32::MAX as u64);
+            assert_eq!(cipher.try_current_pos::<u32>().unwrap(), u32::MAX);
+        }
+
+        #[test]
+        fn seek_past_the_end_fails() {
+            let mut cipher = <ChaCha20Impl as KeyIvInit>::new(&KEY.into(), &NONCE.into());
+            assert!(cipher.try_seek((64u64 << 32) + 1).is_err());
+            assert!(cipher.try_seek(u128::MAX).is_err());
+            assert!(cipher.try_seek(-1i32).is_err());
+
+            let mut legacy = <ChaCha20Legacy as KeyIvInit>::new(&KEY.into(), &[0u8; 8].into());
+            assert!(legacy.try_seek(u64::MAX).is_ok());
+            assert!(legacy.try_seek(1u128 << 64).is_err());
+        }
+
+        #[test]
+        fn try_apply_keystream_stops_at_the_end() {
+            let mut cipher = <ChaCha20Impl as KeyIvInit>::new(&KEY.into(), &NONCE.into());
+            cipher.seek((u32::MAX as u64) * 64);
+
+            let mut buf = [0u8; 65];
+            assert!(cipher.try_apply_keystream(&mut buf).is_err());
+            assert_eq!(buf, [0u8; 65]);
+            assert_eq!(cipher.current_pos::<u64>(), (u32::MAX as u64) * 64);
+
+            cipher.try_apply_keystream(&mut buf[..64]).unwrap();
+            assert_eq!(cipher.current_pos::<u64>(), 64 << 32);
+            assert!(cipher.try_apply_keystream(&mut [0u8; 1]).is_err());
+            assert!(cipher.try_apply_keystream(&mut []).is_ok());
+        }
+    }
+
+    #[cfg(feature = "rand_core")]
+    mod rng_tests {
+        use chacha20imp::rand_core::{CryptoRng, RngCore, SeedableRng};
+        use chacha20imp::rng::{ChaCha12Rng, ChaCha20Rng, ChaCha8Rng, ChaChaRng, ChaChaXRng};
+
+        const SEED: [u8; 32] = [
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28,
+            29, 30, 31, 32,
+        ];
+
+        /// Drive both generators through the same mix of calls and compare every output
+        fn assert_same_output(ours: &mut impl RngCore, theirs: &mut impl RngCore) {
+            for round in 0..200usize {
+                match round % 4 {
+                    0 => assert_eq!(ours.next_u32(), theirs.next_u32()),
+                    1 => assert_eq!(ours.next_u64(), theirs.next_u64()),
+                    _ => {
+                        let len = (round * 37) % 300;
+                        let (mut a, mut b) = (vec![0u8; len], vec![0u8; len]);
+                        ours.fill_bytes(&mut a);
+                        theirs.fill_bytes(&mut b);
+                        assert_eq!(a, b, "fill_bytes of {len} bytes");
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn true_values() {
+            // draft-nir-cfrg-chacha20-poly1305-04 test vectors 1 and 2, as used by rand_chacha
+            let mut rng = ChaChaRng::from_seed([0u8; 32]);
+            let results: Vec<u32> = (0..32).map(|_| rng.next_u32()).collect();
+            assert_eq!(
+                results[..16],
+                [
+                    0xade0b876, 0x903df1a0, 0xe56a5d40, 0x28bd8653, 0xb819d2bd, 0x1aed8da0, 0xccef36a8, 0xc70d778b,
+                    0x7c5941da, 0x8d485751, 0x3fe02477, 0x374ad8b8, 0xf4b8436a, 0x1ca11815, 0x69b687c3, 0x8665eeb2,
+                ]
+            );
+            assert_eq!(
+                results[16..],
+                [
+                    0xbee7079f, 0x7a385155, 0x7c97ba98, 0x0d082d73, 0xa0290fcb, 0x6965e348, 0x3e53c612, 0xed7aee32,
+                    0x7621b729, 0x434ee69c, 0xb03371d5, 0xd539d874, 0x281fed31, 0x45fb0a51, 0x1f0ae1ac, 0x6f4d794b,
+                ]
+            );
+        }
+
+        #[test]
+        fn matches_rand_chacha() {
+            assert_same_output(&mut ChaCha20Rng::from_seed(SEED), &mut rand_chacha::ChaCha20Rng::from_seed(SEED));
+            assert_same_output(&mut ChaCha12Rng::from_seed(SEED), &mut rand_chacha::ChaCha12Rng::from_seed(SEED));
+            assert_same_output(&mut ChaCha8Rng::from_seed(SEED), &mut rand_chacha::ChaCha8Rng::from_seed(SEED));
+        }
+
+        #[test]
+        fn seed_from_u64_matches_rand_chacha() {
+            assert_same_output(&mut ChaChaRng::seed_from_u64(42), &mut rand_chacha::ChaChaRng::seed_from_u64(42));
+        }
+
+        #[test]
+        fn streams_match_rand_chacha() {
+            for stream in [1, 0xdead_beef, u64::MAX] {
+                let mut ours = ChaChaRng::from_seed(SEED);
+                let mut theirs = rand_chacha::ChaChaRng::from_seed(SEED);
+                ours.set_stream(stream);
+                theirs.set_stream(stream);
+                assert_eq!(ours.get_stream(), stream);
+                assert_same_output(&mut ours, &mut theirs);
+            }
+        }
+
+        #[test]
+        fn set_stream_keeps_the_word_position() {
+            let mut ours = ChaChaRng::from_seed(SEED);
+            let mut theirs = rand_chacha::ChaChaRng::from_seed(SEED);
+            ours.fill_bytes(&mut [0u8; 21]);
+            theirs.fill_bytes(&mut [0u8; 21]);
+
+            ours.set_stream(7);
+            theirs.set_stream(7);
+            assert_eq!(ours.get_word_pos(), theirs.get_word_pos());
+            assert_same_output(&mut ours, &mut theirs);
+        }
+
+        #[test]
+        fn word_positions_match_rand_chacha() {
+            let mut ours = ChaChaRng::from_seed(SEED);
+            let mut theirs = rand_chacha::ChaChaRng::from_seed(SEED);
+            for len in [0, 1, 4, 5, 63, 64, 65, 1000] {
+                ours.fill_bytes(&mut vec![0u8; len]);
+                theirs.fill_bytes(&mut vec![0u8; len]);
+                assert_eq!(ours.get_word_pos(), theirs.get_word_pos());
+            }
+
+            for pos in [0u128, 1, 15, 16, 17, 1 << 40, (1 << 68) - 1, (1 << 68) + 5] {
+                ours.set_word_pos(pos);
+                theirs.set_word_pos(pos);
+                assert_eq!(ours.get_word_pos(), theirs.get_word_pos(), "word position {pos}");
+                assert_eq!(ours.next_u64(), theirs.next_u64(), "word position {pos}");
+                assert_eq!(ours.get_word_pos(), theirs.get_word_pos(), "word position {pos}");
+            }
+        }
+
+        #[test]
+        fn set_word_pos_replays_output() {
+            let mut rng = ChaChaRng::from_seed(SEED);
+            rng.next_u32();
+            let pos = rng.get_word_pos();
+            let expected: Vec<u64> = (0..20).map(|_| rng.next_u64()).collect();
+
+            rng.set_word_pos(pos);
+            let replayed: Vec<u64> = (0..20).map(|_| rng.next_u64()).collect();
+            assert_eq!(replayed, expected);
+        }
+
+        #[test]
+        fn get_seed_and_clone() {
+            let mut rng = ChaChaRng::from_seed(SEED);
+            assert_eq!(rng.get_seed(), SEED);
+
+            rng.next_u32();
+            let mut copy = rng.clone();
+            assert_eq!(copy.next_u64(), rng.next_u64());
+        }
+
+        #[test]
+        fn is_a_crypto_rng() {
+            fn assert_crypto_rng<R: CryptoRng + RngCore + SeedableRng>() {}
+            assert_crypto_rng::<ChaChaXRng<20>>();
+            assert_crypto_rng::<ChaCha8Rng>();
+        }
+    }
+
+    mod fast_key_erasure_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        use chacha20imp::fast_key_erasure::{Error, FastKeyErasureRng, BUFFER_SIZE};
+        use std::cell::Cell;
+
+        const SEED: [u8; 32] = [7u8; 32];
+
+        fn no_entropy(_: &mut [u8]) -> Result<(), Error> {
+            Err(Error::EntropyUnavailable)
+        }
+
+        /// One refill of the generator: the next key and the output it buffers
+        fn refill(key: &[u8; 32]) -> ([u8; 32], Vec<u8>) {
+            let mut block = vec![0u8; BUFFER_SIZE];
+            ChaCha20Impl::new(key, &[0u8; 12]).apply_keystream(&mut block);
+            let output = block.split_off(32);
+            (block.try_into().unwrap(), output)
+        }
+
+        fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+            haystack.windows(needle.len()).any(|window| window == needle)
+        }
+
+        #[test]
+        fn output_follows_the_key_erasure_chain() {
+            let (key1, out1) = refill(&SEED);
+            let (_, out2) = refill(&key1);
+            let expected = [out1, out2].concat();
+
+            let mut rng = FastKeyErasureRng::from_seed(SEED, no_entropy);
+            let mut output = vec![0u8; expected.len()];
+            for chunk in output.chunks_mut(97) {
+                rng.fill_bytes(chunk).unwrap();
+            }
+            assert_eq!(output, expected);
+        }
+
+        #[test]
+        fn old_outputs_cannot_be_recomputed_from_the_new_state() {
+            let mut rng = FastKeyErasureRng::from_seed(SEED, no_entropy);
+            let mut old = [0u8; 100];
+            rng.fill_bytes(&mut old).unwrap();
+
+            // After the request the state is the replaced key and the unused rest of the buffer
+            let (key1, out1) = refill(&SEED);
+            assert_eq!(&out1[..100], &old[..]);
+            let buffered = out1[100..].to_vec();
+            assert_ne!(key1, SEED);
+
+            // That state determines everything the generator outputs from now on...
+            let (_, out2) = refill(&key1);
+            let mut future = vec![0u8; buffered.len() + out2.len()];
+            rng.fill_bytes(&mut future).unwrap();
+            assert_eq!(future, [buffered.clone(), out2].concat());
+
+            // ...but the old output is in neither the buffer nor any keystream of the new key
+            let mut keystream = vec![0u8; 1 << 16];
+            ChaCha20Impl::new(&key1, &[0u8; 12]).apply_keystream(&mut keystream);
+            for needle in old.chunks_exact(16) {
+                assert!(!contains(&buffered, needle));
+                assert!(!contains(&keystream, needle));
+                assert!(!contains(&key1, &needle[..8]));
+            }
+        }
+
+        #[test]
+        fn every_refill_replaces_the_key() {
+            let mut key = SEED;
+            let mut keys = vec![key];
+            for _ in 0..16 {
+                key = refill(&key).0;
+                assert!(!keys.contains(&key));
+                keys.push(key);
+            }
+
+            // 16 refills of output, followed by the output of the 17th key
+            let mut rng = FastKeyErasureRng::from_seed(SEED, no_entropy);
+            rng.fill_bytes(&mut vec![0u8; 16 * (BUFFER_SIZE - 32)]).unwrap();
+            let mut next = [0u8; 32];
+            rng.fill_bytes(&mut next).unwrap();
+            assert_eq!(&next[..], &refill(&key).1[..32]);
+        }
+
+        #[test]
+        fn reseeds_after_the_interval() {
+            let calls = Cell::new(0);
+            let entropy = |dest: &mut [u8]| {
+                calls.set(calls.get() + 1);
+                dest.fill(calls.get());
+                Ok(())
+            };
+
+            let mut rng = FastKeyErasureRng::from_seed(SEED, entropy).with_reseed_interval(1000);
+            let mut reference = FastKeyErasureRng::from_seed(SEED, no_entropy);
+
+            let mut a = [0u8; 100];
+            let mut b = [0u8; 100];
+            for _ in 0..10 {
+                rng.fill_bytes(&mut a).unwrap();
+                reference.fill_bytes(&mut b).unwrap();
+                assert_eq!(a, b);
+            }
+            assert_eq!(calls.get(), 0);
+
+            // The interval is reached, so the next request mixes in entropy first
+            rng.fill_bytes(&mut a).unwrap();
+            reference.fill_bytes(&mut b).unwrap();
+            assert_eq!(calls.get(), 1);
+            assert_ne!(a, b);
+
+            for _ in 0..10 {
+                rng.fill_bytes(&mut a).unwrap();
+            }
+            assert_eq!(calls.get(), 2);
+        }
+
+        #[test]
+        fn reseeds_in_the_middle_of_a_long_request() {
+            let calls = Cell::new(0);
+            let entropy = |dest: &mut [u8]| {
+                calls.set(calls.get() + 1);
+                dest.fill(calls.get());
+                Ok(())
+            };
+
+            let mut rng = FastKeyErasureRng::from_seed(SEED, entropy).with_reseed_interval(1000);
+            let mut reference = FastKeyErasureRng::from_seed(SEED, no_entropy);
+
+            let mut output = vec![0u8; 2500];
+            rng.fill_bytes(&mut output).unwrap();
+            assert_eq!(calls.get(), 2);
+
+            let mut expected = vec![0u8; 1000];
+            reference.fill_bytes(&mut expected).unwrap();
+            assert_eq!(&output[..1000], &expected[..]);
+            reference.fill_bytes(&mut expected).unwrap();
+            assert_ne!(&output[1000..2000], &expected[..]);
+
+            // The failed reseed after 1000 bytes leaves them written
+            let mut rng = FastKeyErasureRng::from_seed(SEED, no_entropy).with_reseed_interval(1000);
+            let mut output = vec![0u8; 1500];
+            assert_eq!(rng.fill_bytes(&mut output), Err(Error::EntropyUnavailable));
+            assert_eq!(&output[1000..], &[0u8; 500][..]);
+        }
+
+        #[test]
+        fn reseed_depends_on_the_entropy() {
+            let mut a = FastKeyErasureRng::from_seed(SEED, |dest: &mut [u8]| {
+                dest.fill(1);
+                Ok(())
+            });
+            let mut b = FastKeyErasureRng::from_seed(SEED, |dest: &mut [u8]| {
+                dest.fill(2);
+                Ok(())
+            });
+            a.reseed().unwrap();
+            b.reseed().unwrap();
+            assert_ne!(a.gen_key().unwrap(), b.gen_key().unwrap());
+        }
+
+        #[test]
+        fn reseed_discards_buffered_output() {
+            let zero_entropy = |dest: &mut [u8]| {
+                dest.fill(0);
+                Ok(())
+            };
+            let mut rng = FastKeyErasureRng::from_seed(SEED, zero_entropy);
+            rng.fill_bytes(&mut [0u8; 10]).unwrap();
+            rng.reseed().unwrap();
+
+            let (_, buffered) = refill(&SEED);
+            let mut output = [0u8; 64];
+            rng.fill_bytes(&mut output).unwrap();
+            assert_ne!(&output[..], &buffered[10..74]);
+        }
+
+        #[test]
+        fn entropy_failure_is_reported() {
+            assert_eq!(FastKeyErasureRng::new(no_entropy).err(), Some(Error::EntropyUnavailable));
+
+            let mut rng = FastKeyErasureRng::from_seed(SEED, no_entropy).with_reseed_interval(32);
+            assert_eq!(rng.reseed(), Err(Error::EntropyUnavailable));
+            rng.gen_key().unwrap();
+
+            let mut dest = [0u8; 16];
+            assert_eq!(rng.fill_bytes(&mut dest), Err(Error::EntropyUnavailable));
+            assert_eq!(dest, [0u8; 16]);
+            assert_eq!(Error::EntropyUnavailable.to_string(), "Entropy source is unavailable");
+        }
+
+        #[test]
+        fn seeded_from_the_entropy_source() {
+            let entropy = |dest: &mut [u8]| {
+                dest.copy_from_slice(&SEED[..dest.len()]);
+                Ok(())
+            };
+            let mut rng = FastKeyErasureRng::new(entropy).unwrap();
+            assert_eq!(rng.gen_key().unwrap()[..], refill(&SEED).1[..32]);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    mod io_tests {
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        use chacha20imp::io::{DecryptingReader, EncryptingWriter, StreamingReader, CHUNK_SIZE};
+        use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+        const KEY: [u8; 32] = [0x42; 32];
+        const NONCE: [u8; 12] = [0x24; 12];
+
+        fn cipher() -> ChaCha20Impl {
+            ChaCha20Impl::new(&KEY, &NONCE)
+        }
+
+        fn plaintext(len: usize) -> Vec<u8> {
+            (0..len).map(|i| (i * 31 % 251) as u8).collect()
+        }
+
+        fn encrypt(data: &[u8]) -> Vec<u8> {
+            let mut out = data.to_vec();
+            cipher().apply_keystream(&mut out);
+            out
+        }
+
+        /// Writer that takes at most `max` bytes per call and fails every third call
+        struct ShortWriter {
+            data: Vec<u8>,
+            max: usize,
+            calls: usize,
+            flushes: usize,
+        }
+
+        impl Write for ShortWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.calls += 1;
+                if self.calls.is_multiple_of(3) {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+                }
+                let len = buf.len().min(self.max);
+                self.data.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes += 1;
+                Ok(())
+            }
+        }
+
+        /// Reader that returns at most `max` bytes per call
+        struct ShortReader<R> {
+            inner: R,
+            max: usize,
+        }
+
+        impl<R: Read> Read for ShortReader<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = buf.len().min(self.max);
+                self.inner.read(&mut buf[..len])
+            }
+        }
+
+        #[test]
+        fn writer_matches_apply_keystream() {
+            let data = plaintext(10_000);
+            let mut writer = EncryptingWriter::new(Vec::new(), cipher());
+            for chunk in data.chunks(97) {
+                writer.write_all(chunk).unwrap();
+            }
+            assert_eq!(writer.cipher().current_pos(), data.len() as u64);
+            assert_eq!(writer.into_inner(), encrypt(&data));
+        }
+
+        #[test]
+        fn writer_handles_short_and_failed_writes() {
+            let data = plaintext(5000);
+            let inner = ShortWriter {
+                data: Vec::new(),
+                max: 7,
+                calls: 0,
+                flushes: 0,
+            };
+            let mut writer = EncryptingWriter::new(inner, cipher());
+            writer.write_all(&data).unwrap();
+            writer.flush().unwrap();
+
+            let inner = writer.into_inner();
+            assert_eq!(inner.flushes, 1);
+            assert_eq!(inner.data, encrypt(&data));
+        }
+
+        #[test]
+        fn writer_retries_after_an_error() {
+            let data = plaintext(200);
+            let inner = ShortWriter {
+                data: Vec::new(),
+                max: usize::MAX,
+                calls: 2,
+                flushes: 0,
+            };
+            let mut writer = EncryptingWriter::new(inner, cipher());
+
+            let error = writer.write(&data).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+            assert_eq!(writer.cipher().current_pos(), 0);
+
+            assert_eq!(writer.write(&data).unwrap(), data.len());
+            assert_eq!(writer.get_ref().data, encrypt(&data));
+        }
+
+        #[test]
+        fn writer_takes_at_most_one_chunk_per_call() {
+            let data = plaintext(3 * CHUNK_SIZE);
+            let mut writer = EncryptingWriter::new(Vec::new(), cipher());
+            assert_eq!(writer.write(&data).unwrap(), CHUNK_SIZE);
+            assert_eq!(writer.write(&[]).unwrap(), 0);
+            assert_eq!(writer.get_ref().len(), CHUNK_SIZE);
+        }
+
+        #[test]
+        fn writer_refuses_to_wrap_the_counter() {
+            let mut cipher = cipher();
+            cipher.seek(u32::MAX);
+            let mut writer = EncryptingWriter::new(Vec::new(), cipher);
+            writer.write_all(&[0u8; 64]).unwrap();
+            assert!(writer.write(&[0u8; 1]).is_err());
+            assert_eq!(writer.get_ref().len(), 64);
+        }
+
+        #[test]
+        fn reader_round_trip() {
+            let data = plaintext(10_000);
+            let inner = ShortReader {
+                inner: Cursor::new(encrypt(&data)),
+                max: 13,
+            };
+            let mut reader = DecryptingReader::new(inner, cipher());
+            let mut decrypted = Vec::new();
+            reader.read_to_end(&mut decrypted).unwrap();
+            assert_eq!(decrypted, data);
+            assert_eq!(reader.cipher().current_pos(), data.len() as u64);
+            assert_eq!(reader.into_inner().inner.position(), data.len() as u64);
+        }
+
+        #[test]
+        fn writer_and_reader_work_with_other_ciphers() {
+            use chacha20imp::xchacha20::XChaCha20;
+
+            let data = plaintext(1000);
+            let mut writer = EncryptingWriter::new(Vec::new(), XChaCha20::new(&KEY, &[7u8; 24]));
+            writer.write_all(&data).unwrap();
+            let ciphertext = writer.into_inner();
+            assert_ne!(ciphertext, data);
+
+            let mut reader = DecryptingReader::new(&ciphertext[..], XChaCha20::new(&KEY, &[7u8; 24]));
+            let mut decrypted = Vec::new();
+            reader.read_to_end(&mut decrypted).unwrap();
+            assert_eq!(decrypted, data);
+        }
+
+        #[test]
+        fn streaming_reader_seeks() {
+            let data = plaintext(1000);
+            let mut reader = StreamingReader::new(Cursor::new(encrypt(&data)), cipher());
+
+            for &offset in &[0u64, 1, 63, 64, 65, 500, 999] {
+                assert_eq!(reader.seek(SeekFrom::Start(offset)).unwrap(), offset);
+                let mut buf = [0u8; 10];
+                let len = reader.read(&mut buf).unwrap();
+                assert_eq!(&buf[..len], &data[offset as usize..offset as usize + len]);
+            }
+
+            assert_eq!(reader.seek(SeekFrom::End(-100)).unwrap(), 900);
+            let mut tail = Vec::new();
+            reader.read_to_end(&mut tail).unwrap();
+            assert_eq!(tail, &data[900..]);
+
+            assert_eq!(reader.seek(SeekFrom::Current(-130)).unwrap(), 870);
+            let mut buf = [0u8; 70];
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[870..940]);
+            assert_eq!(reader.stream_position().unwrap(), 940);
+            assert_eq!(reader.cipher().current_pos(), 940);
+        }
+
+        #[test]
+        fn streaming_reader_rejects_offsets_past_the_keystream() {
+            let data = plaintext(200);
+            let mut reader = StreamingReader::new(Cursor::new(encrypt(&data)), cipher());
+            reader.seek(SeekFrom::Start(100)).unwrap();
+
+            let error = reader.seek(SeekFrom::Start((64 << 32) + 1)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(reader.seek(SeekFrom::Current(-101)).is_err());
+
+            let mut buf = [0u8; 20];
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[100..120]);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    mod tokio_tests {
+        use chacha20imp::async_io::{AsyncDecryptingReader, AsyncEncryptingWriter};
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        use std::io;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+        use tokio::io::{duplex, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+        const KEY: [u8; 32] = [0x42; 32];
+        const NONCE: [u8; 12] = [0x24; 12];
+
+        fn cipher() -> ChaCha20Impl {
+            ChaCha20Impl::new(&KEY, &NONCE)
+        }
+
+        fn plaintext(len: usize) -> Vec<u8> {
+            (0..len).map(|i| (i * 31 % 251) as u8).collect()
+        }
+
+        fn encrypt(data: &[u8]) -> Vec<u8> {
+            let mut out = data.to_vec();
+            cipher().apply_keystream(&mut out);
+            out
+        }
+
+        /// Writer that returns `Pending` on every other call and takes at most 5 bytes otherwise
+        struct StutteringWriter {
+            data: Vec<u8>,
+            pending: bool,
+        }
+
+        impl AsyncWrite for StutteringWriter {
+            fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+                self.pending = !self.pending;
+                if self.pending {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                let len = buf.len().min(5);
+                self.data.extend_from_slice(&buf[..len]);
+                Poll::Ready(Ok(len))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        /// Reader that fails once, then returns at most 3 bytes per call
+        struct FlakyReader {
+            data: Vec<u8>,
+            failed: bool,
+        }
+
+        impl AsyncRead for FlakyReader {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &mut tokio::io::ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                if !self.failed {
+                    self.failed = true;
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted")));
+                }
+                let len = self.data.len().min(3).min(buf.remaining());
+                buf.put_slice(&self.data[..len]);
+                self.data.drain(..len);
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        #[tokio::test]
+        async fn writer_over_duplex_matches_apply_keystream() {
+            let data = plaintext(10_000);
+            let (client, mut server) = duplex(7);
+
+            let write = async {
+                let mut writer = AsyncEncryptingWriter::new(client, cipher());
+                for chunk in data.chunks(100) {
+                    writer.write_all(chunk).await.unwrap();
+                }
+                writer.shutdown().await.unwrap();
+                assert_eq!(writer.cipher().current_pos(), data.len() as u64);
+            };
+            let read = async {
+                let mut ciphertext = Vec::new();
+                server.read_to_end(&mut ciphertext).await.unwrap();
+                ciphertext
+            };
+
+            let ((), ciphertext) = tokio::join!(write, read);
+            assert_eq!(ciphertext, encrypt(&data));
+        }
+
+        #[tokio::test]
+        async fn reader_over_duplex_decrypts_partial_reads() {
+            let data = plaintext(10_000);
+            let ciphertext = encrypt(&data);
+            let (mut client, server) = duplex(11);
+
+            let write = async {
+                client.write_all(&ciphertext).await.unwrap();
+                client.shutdown().await.unwrap();
+            };
+            let read = async {
+                let mut reader = AsyncDecryptingReader::new(server, cipher());
+                let mut decrypted = Vec::new();
+                let mut buf = [0u8; 13];
+                loop {
+                    let read = reader.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    decrypted.extend_from_slice(&buf[..read]);
+                }
+                assert_eq!(reader.cipher().current_pos(), data.len() as u64);
+                decrypted
+            };
+
+            let ((), decrypted) = tokio::join!(write, read);
+            assert_eq!(decrypted, data);
+        }
+
+        #[tokio::test]
+        async fn round_trip_through_both_adapters() {
+            let data = plaintext(50_000);
+            let (client, server) = duplex(64);
+
+            let write = async {
+                let mut writer = AsyncEncryptingWriter::new(client, cipher());
+                writer.write_all(&data).await.unwrap();
+                writer.shutdown().await.unwrap();
+            };
+            let read = async {
+                let mut reader = AsyncDecryptingReader::new(server, cipher());
+                let mut decrypted = Vec::new();
+                reader.read_to_end(&mut decrypted).await.unwrap();
+                decrypted
+            };
+
+            let ((), decrypted) = tokio::join!(write, read);
+            assert_eq!(decrypted, data);
+        }
+
+        #[tokio::test]
+        async fn pending_and_short_writes_keep_the_position() {
+            let data = plaintext(1000);
+            let inner = StutteringWriter {
+                data: Vec::new(),
+                pending: false,
+            };
+            let mut writer = AsyncEncryptingWriter::new(inner, cipher());
+            writer.write_all(&data).await.unwrap();
+            writer.flush().await.unwrap();
+            assert_eq!(writer.cipher().current_pos(), data.len() as u64);
+            assert_eq!(writer.into_inner().data, encrypt(&data));
+        }
+
+        #[tokio::test]
+        async fn failed_reads_keep_the_position() {
+            let data = plaintext(100);
+            let inner = FlakyReader {
+                data: encrypt(&data),
+                failed: false,
+            };
+            let mut reader = AsyncDecryptingReader::new(inner, cipher());
+            let mut buf = [0u8; 100];
+            assert!(reader.read(&mut buf).await.is_err());
+            assert_eq!(reader.cipher().current_pos(), 0);
+
+            reader.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf[..], &data[..]);
+        }
+    }
+
+    #[cfg(feature = "futures-io")]
+    mod futures_io_tests {
+        use chacha20imp::async_io::{AsyncDecryptingReader, AsyncEncryptingWriter};
+        use chacha20imp::chacha20::{ChaCha20, ChaCha20Impl};
+        use futures::executor::block_on;
+        use futures::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::io::duplex;
+        use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+        const KEY: [u8; 32] = [0x42; 32];
+        const NONCE: [u8; 12] = [0x24; 12];
+
+        fn cipher() -> ChaCha20Impl {
+            ChaCha20Impl::new(&KEY, &NONCE)
+        }
+
+        fn plaintext(len: usize) -> Vec<u8> {
+            (0..len).map(|i| (i * 31 % 251) as u8).collect()
+        }
+
+        fn encrypt(data: &[u8]) -> Vec<u8> {
+            let mut out = data.to_vec();
+            cipher().apply_keystream(&mut out);
+            out
+        }
+
+        #[test]
+        fn writer_over_duplex_matches_apply_keystream() {
+            let data = plaintext(10_000);
+            let (client, server) = duplex(7);
+
+            let write = async {
+                let mut writer = AsyncEncryptingWriter::new(client.compat_write(), cipher());
+                for chunk in data.chunks(100) {
+                    writer.write_all(chunk).await.unwrap();
+                }
+                writer.close().await.unwrap();
+            };
+            let read = async {
+                let mut ciphertext = Vec::new();
+                server.compat().read_to_end(&mut ciphertext).await.unwrap();
+                ciphertext
+            };
+
+            let ((), ciphertext) = block_on(async { futures::join!(write, read) });
+            assert_eq!(ciphertext, encrypt(&data));
+        }
+
+        #[test]
+        fn reader_over_duplex_decrypts_partial_reads() {
+            let data = plaintext(10_000);
+            let ciphertext = encrypt(&data);
+            let (client, server) = duplex(11);
+
+            let write = async {
+                let mut client = client.compat_write();
+                client.write_all(&ciphertext).await.unwrap();
+                client.close().await.unwrap();
+            };
+            let read = async {
+                let mut reader = AsyncDecryptingReader::new(server.compat(), cipher());
+                let mut decrypted = Vec::new();
+                let mut buf = [0u8; 13];
+                loop {
+                    let read = reader.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    decrypted.extend_from_slice(&buf[..read]);
+                }
+                decrypted
+            };
+
+            let ((), decrypted) = block_on(async { futures::join!(write, read) });
+            assert_eq!(decrypted, data);
+        }
+
+        #[test]
+        fn round_trip_through_both_adapters() {
+            let data = plaintext(50_000);
+            let (client, server) = duplex(64);
+
+            let write = async {
+                let mut writer = AsyncEncryptingWriter::new(client.compat_write(), cipher());
+                writer.write_all(&data).await.unwrap();
+                writer.close().await.unwrap();
+            };
+            let read = async {
+                let mut reader = AsyncDecryptingReader::new(server.compat(), cipher());
+                let mut decrypted = Vec::new();
+                reader.read_to_end(&mut decrypted).await.unwrap();
+                decrypted
+            };
+
+            let ((), decrypted) = block_on(async { futures::join!(write, read) });
+            assert_eq!(decrypted, data);
+        }
+    }
+
+    mod stream_tests {
+        use chacha20imp::stream::{Error, StreamDecryptor, StreamEncryptor};
+
+        const KEY: [u8; 32] = [0x42; 32];
+        const PREFIX: [u8; 7] = [1, 2, 3, 4, 5, 6, 7];
+
+        /// Encrypt three segments in place, returning them with their tags
+        fn encrypt_segments() -> Vec<(Vec<u8>, [u8; 16])> {
+            let mut encryptor = StreamEncryptor::new(&KEY, &PREFIX);
+            let mut segments = Vec::new();
+            for (i, plaintext) in [&b"first"[..], b"second", b"third"].into_iter().enumerate() {
+                let mut buffer = plaintext.to_vec();
+                if i < 2 {
+                    let tag = encryptor.encrypt_next_in_place(b"aad", &mut buffer).unwrap();
+                    segments.push((buffer, tag));
+                } else {
+                    let tag = encryptor.encrypt_last_in_place(b"aad", &mut buffer);
+                    segments.push((buffer, tag));
+                    break;
+                }
+            }
+            segments
+        }
+
+        #[test]
+        fn round_trip_in_place() {
+            let mut segments = encrypt_segments();
+            let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+            let (last, rest) = segments.split_last_mut().unwrap();
+            for (buffer, tag) in rest.iter_mut() {
+                decryptor.decrypt_next_in_place(b"aad", buffer, tag).unwrap();
+            }
+            decryptor.decrypt_last_in_place(b"aad", &mut last.0, &last.1).unwrap();
+
+            assert_eq!(segments[0].0, b"first");
+            assert_eq!(segments[1].0, b"second");
+            assert_eq!(segments[2].0, b"third");
+        }
+
+        #[test]
+        fn reordered_segments_are_rejected() {
+            let segments = encrypt_segments();
+            let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+
+            let (mut buffer, tag) = segments[1].clone();
+            assert_eq!(decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag), Err(Error::InvalidTag));
+            assert_eq!(buffer, segments[1].0);
+
+            // The decryptor is still at segment 0
+            let (mut buffer, tag) = segments[0].clone();
+            decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag).unwrap();
+            assert_eq!(buffer, b"first");
+        }
+
+        #[test]
+        fn truncation_and_extension_are_rejected() {
+            let segments = encrypt_segments();
+
+            // Ending the stream after a segment that is not the last one
+            let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+            let (mut buffer, tag) = segments[0].clone();
+            decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag).unwrap();
+            let (mut buffer, tag) = segments[1].clone();
+            assert_eq!(decryptor.decrypt_last_in_place(b"aad", &mut buffer, &tag), Err(Error::InvalidTag));
+
+            // Continuing after the last segment
+            let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+            for (mut buffer, tag) in segments[..2].iter().cloned() {
+                decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag).unwrap();
+            }
+            let (mut buffer, tag) = segments[2].clone();
+            assert_eq!(decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag), Err(Error::InvalidTag));
+        }
+
+        #[test]
+        fn tampering_is_rejected() {
+            let segments = encrypt_segments();
+
+            let (mut buffer, tag) = segments[0].clone();
+            buffer[0] ^= 1;
+            let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+            assert_eq!(decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag), Err(Error::InvalidTag));
+
+            let (mut buffer, tag) = segments[0].clone();
+            assert_eq!(decryptor.decrypt_next_in_place(b"bad", &mut buffer, &tag), Err(Error::InvalidTag));
+
+            let (mut buffer, tag) = segments[0].clone();
+            let mut decryptor = StreamDecryptor::new(&KEY, &[0u8; 7]);
+            assert_eq!(decryptor.decrypt_next_in_place(b"aad", &mut buffer, &tag), Err(Error::InvalidTag));
+        }
+
+        #[cfg(feature = "alloc")]
+        #[test]
+        fn vec_api_appends_the_tag() {
+            let mut encryptor = StreamEncryptor::new(&KEY, &PREFIX);
+            let first = encryptor.encrypt_next(b"", b"Hello, ").unwrap();
+            let last = encryptor.encrypt_last(b"", b"");
+            assert_eq!(first.len(), 7 + 16);
+            assert_eq!(last.len(), 16);
+
+            let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+            assert_eq!(decryptor.decrypt_next(b"", &first).unwrap(), b"Hello, ");
+            assert_eq!(decryptor.decrypt_next(b"", &[0u8; 15]), Err(Error::Truncated));
+            assert_eq!(decryptor.decrypt_last(b"", &last).unwrap(), b"");
+        }
+
+        #[cfg(feature = "alloc")]
+        #[test]
+        fn matches_rustcrypto_stream_be32() {
+            use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+            use chacha20poly1305::aead::{KeyInit, Payload};
+            use chacha20poly1305::ChaCha20Poly1305;
+
+            let aead = || ChaCha20Poly1305::new(&KEY.into());
+            let plaintexts: [&[u8]; 3] = [&[1u8; 100], &[2u8; 100], b"tail"];
+
+            let mut theirs = EncryptorBE32::from_aead(aead(), &PREFIX.into());
+            let mut ours = StreamEncryptor::new(&KEY, &PREFIX);
+            for plaintext in &plaintexts[..2] {
+                let expected = theirs.encrypt_next(Payload { msg: plaintext, aad: b"aad" }).unwrap();
+                assert_eq!(ours.encrypt_next(b"aad", plaintext).unwrap(), expected);
+            }
+            let expected = theirs.encrypt_last(Payload { msg: plaintexts[2], aad: b"aad" }).unwrap();
+            assert_eq!(ours.encrypt_last(b"aad", plaintexts[2]), expected);
+
+            let mut ours = StreamEncryptor::new(&KEY, &PREFIX);
+            let mut theirs = DecryptorBE32::from_aead(aead(), &PREFIX.into());
+            let ciphertext = ours.encrypt_next(b"", plaintexts[0]).unwrap();
+            assert_eq!(theirs.decrypt_next(&ciphertext[..]).unwrap(), plaintexts[0]);
+            let ciphertext = ours.encrypt_last(b"", plaintexts[2]);
+            assert_eq!(theirs.decrypt_last(&ciphertext[..]).unwrap(), plaintexts[2]);
+        }
+
+        #[cfg(feature = "alloc")]
+        #[test]
+        fn x_variant_matches_rustcrypto_stream_be32() {
+            use chacha20poly1305::aead::stream::EncryptorBE32;
+            use chacha20poly1305::aead::{KeyInit, Payload};
+            use chacha20poly1305::XChaCha20Poly1305;
+
+            let prefix: [u8; 19] = core::array::from_fn(|i| i as u8 + 1);
+            let plaintexts: [&[u8]; 3] = [&[1u8; 100], &[2u8; 100], b"tail"];
+
+            let mut theirs = EncryptorBE32::from_aead(XChaCha20Poly1305::new(&KEY.into()), &prefix.into());
+            let mut ours = StreamEncryptor::new_x(&KEY, &prefix);
+            for plaintext in &plaintexts[..2] {
+                let expected = theirs.encrypt_next(Payload { msg: plaintext, aad: b"aad" }).unwrap();
+                assert_eq!(ours.encrypt_next(b"aad", plaintext).unwrap(), expected);
+            }
+            let expected = theirs.encrypt_last(Payload { msg: plaintexts[2], aad: b"aad" }).unwrap();
+            assert_eq!(ours.encrypt_last(b"aad", plaintexts[2]), expected);
+
+            let mut ours = StreamEncryptor::new_x(&KEY, &prefix);
+            let mut decryptor = StreamDecryptor::new_x(&KEY, &prefix);
+            let ciphertext = ours.encrypt_next(b"aad", plaintexts[0]).unwrap();
+            assert_eq!(decryptor.decrypt_next(b"aad", &ciphertext).unwrap(), plaintexts[0]);
+            assert_eq!(decryptor.decrypt_last(b"aad", &expected), Err(Error::InvalidTag));
+        }
+
+        #[cfg(feature = "std")]
+        mod adapters {
+            use chacha20imp::stream::{OpeningReader, SealingWriter, StreamDecryptor, StreamEncryptor};
+            use std::io::{self, Read, Write};
+
+            const KEY: [u8; 32] = [0x42; 32];
+            const PREFIX: [u8; 7] = [1, 2, 3, 4, 5, 6, 7];
+            const SEGMENT: usize = 100;
+
+            fn plaintext(len: usize) -> Vec<u8> {
+                (0..len).map(|i| (i * 31 % 251) as u8).collect()
+            }
+
+            fn seal(data: &[u8]) -> Vec<u8> {
+                let mut writer =
+                    SealingWriter::new(Vec::new(), StreamEncryptor::new(&KEY, &PREFIX)).with_segment_size(SEGMENT);
+                for chunk in data.chunks(37) {
+                    writer.write_all(chunk).unwrap();
+                }
+                writer.finish().unwrap()
+            }
+
+            fn open(ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+                let mut reader =
+                    OpeningReader::new(ciphertext, StreamDecryptor::new(&KEY, &PREFIX)).with_segment_size(SEGMENT);
+                let mut plaintext = Vec::new();
+                let mut buf = [0u8; 23];
+                loop {
+                    let read = reader.read(&mut buf)?;
+                    if read == 0 {
+                        return Ok(plaintext);
+                    }
+                    plaintext.extend_from_slice(&buf[..read]);
+                }
+            }
+
+            /// Writer that takes at most 3 bytes per call and is interrupted every other call
+            struct ShortWriter {
+                data: Vec<u8>,
+                interrupt: bool,
+            }
+
+            impl Write for ShortWriter {
+                fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                    self.interrupt = !self.interrupt;
+                    if self.interrupt {
+                        return Err(io::ErrorKind::Interrupted.into());
+                    }
+                    let len = buf.len().min(3);
+                    self.data.extend_from_slice(&buf[..len]);
+                    Ok(len)
+                }
+
+                fn flush(&mut self) -> io::Result<()> {
+                    Ok(())
+                }
+            }
+
+            #[test]
+            fn round_trip_for_every_length() {
+                for len in [0, 1, SEGMENT - 1, SEGMENT, SEGMENT + 1, 3 * SEGMENT, 1000] {
+                    let data = plaintext(len);
+                    let ciphertext = seal(&data);
+                    let segments = len.div_ceil(SEGMENT).max(1);
+                    assert_eq!(ciphertext.len(), len + 16 * segments, "length {}", len);
+                    assert_eq!(open(&ciphertext).unwrap(), data, "length {}", len);
+                }
+            }
+
+            #[test]
+            fn writer_matches_the_push_api() {
+                let data = plaintext(250);
+                let mut encryptor = StreamEncryptor::new(&KEY, &PREFIX);
+                let mut expected = encryptor.encrypt_next(b"", &data[..100]).unwrap();
+                expected.extend(encryptor.encrypt_next(b"", &data[100..200]).unwrap());
+                expected.extend(encryptor.encrypt_last(b"", &data[200..]));
+                assert_eq!(seal(&data), expected);
+            }
+
+            #[test]
+            fn writer_handles_short_writes() {
+                let data = plaintext(1000);
+                let inner = ShortWriter {
+                    data: Vec::new(),
+                    interrupt: false,
+                };
+                let mut writer =
+                    SealingWriter::new(inner, StreamEncryptor::new(&KEY, &PREFIX)).with_segment_size(SEGMENT);
+                writer.write_all(&data).unwrap();
+                writer.flush().unwrap();
+                assert_eq!(writer.finish().unwrap().data, seal(&data));
+            }
+
+            #[test]
+            fn truncated_streams_are_rejected() {
+                let data = plaintext(300);
+                let ciphertext = seal(&data);
+
+                for len in [0, 10, 116, 200, 232, 250, ciphertext.len() - 1] {
+                    let error = open(&ciphertext[..len]).unwrap_err();
+                    assert_eq!(error.kind(), io::ErrorKind::InvalidData, "length {}", len);
+                }
+            }
+
+            #[test]
+            fn extended_streams_are_rejected() {
+                let data = plaintext(300);
+                let mut ciphertext = seal(&data);
+                ciphertext.extend_from_slice(&seal(&data)[..116]);
+                assert!(open(&ciphertext).is_err());
+            }
+
+            #[test]
+            fn tampered_segments_are_never_returned() {
+                let data = plaintext(300);
+                let mut ciphertext = seal(&data);
+                ciphertext[150] ^= 1;
+
+                let mut reader =
+                    OpeningReader::new(&ciphertext[..], StreamDecryptor::new(&KEY, &PREFIX)).with_segment_size(SEGMENT);
+                let mut plaintext = Vec::new();
+                let error = reader.read_to_end(&mut plaintext).unwrap_err();
+                assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+                assert_eq!(plaintext, &data[..SEGMENT]);
+
+                // The error sticks
+                assert!(reader.read(&mut [0u8; 10]).is_err());
+            }
+
+            #[test]
+            fn swapped_segments_are_rejected() {
+                let data = plaintext(300);
+                let ciphertext = seal(&data);
+                let mut swapped = ciphertext[116..232].to_vec();
+                swapped.extend_from_slice(&ciphertext[..116]);
+                swapped.extend_from_slice(&ciphertext[232..]);
+                assert!(open(&swapped).is_err());
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    mod container_tests {
+        use chacha20imp::container::{Algorithm, Error, Header, KdfParams, MAX_SEGMENT_SIZE};
+
+        const KEY: [u8; 32] = [0x42; 32];
+        const SALT: [u8; 24] = [0x11; 24];
+        const NONCE: [u8; 19] = [0x22; 19];
+
+        fn headers() -> [Header; 3] {
+            [
+                Header::new(Algorithm::ChaCha20Poly1305, SALT, NONCE),
+                Header::new(Algorithm::XChaCha20Poly1305, SALT, NONCE).with_segment_size(100),
+                Header::new(Algorithm::XChaCha20Poly1305, SALT, NONCE).with_kdf(KdfParams::new(1, b"params").unwrap()),
+            ]
+        }
+
+        #[test]
+        fn headers_round_trip() {
+            for header in headers() {
+                let bytes = header.encode(&KEY);
+                assert_eq!(bytes.len(), header.encoded_len());
+
+                let (parsed, len) = Header::parse(&bytes).unwrap();
+                assert_eq!(len, bytes.len());
+                assert_eq!(parsed.algorithm(), header.algorithm());
+                assert_eq!(parsed.segment_size(), header.segment_size());
+                assert_eq!(parsed.salt(), header.salt());
+                assert_eq!(parsed.nonce_prefix(), header.nonce_prefix());
+                assert_eq!(parsed.kdf(), header.kdf());
+                assert_eq!(parsed.verify(&KEY), Ok(()));
+                assert_eq!(parsed.encode(&KEY), bytes);
+            }
+
+            let header = Header::new(Algorithm::ChaCha20Poly1305, SALT, NONCE);
+            assert_eq!(header.nonce_prefix(), &NONCE[..7]);
+        }
+
+        #[test]
+        fn parse_ignores_trailing_data() {
+            let mut bytes = headers()[0].encode(&KEY);
+            let len = bytes.len();
+            bytes.extend_from_slice(b"segments");
+            assert_eq!(Header::parse(&bytes).unwrap().1, len);
+        }
+
+        #[test]
+        fn wrong_key_is_reported() {
+            for header in headers() {
+                let (parsed, _) = Header::parse(&header.encode(&KEY)).unwrap();
+                assert_eq!(parsed.verify(&[0x43; 32]), Err(Error::WrongKey));
+            }
+        }
+
+        #[test]
+        fn modified_headers_are_rejected() {
+            for header in headers() {
+                let bytes = header.encode(&KEY);
+                for i in 0..bytes.len() {
+                    let mut modified = bytes.clone();
+                    modified[i] ^= 0x01;
+                    let result = Header::parse(&modified).and_then(|(parsed, _)| parsed.verify(&KEY));
+                    assert!(result.is_err(), "flipped byte {}", i);
+                }
+            }
+        }
+
+        #[test]
+        fn invalid_fields_are_reported() {
+            let bytes = headers()[0].encode(&KEY);
+            let with = |i: usize, value: u8| {
+                let mut modified = bytes.clone();
+                modified[i] = value;
+                Header::parse(&modified).map(|_| ())
+            };
+
+            assert_eq!(with(0, b'X'), Err(Error::InvalidMagic));
+            assert_eq!(with(8, 2), Err(Error::UnsupportedVersion));
+            assert_eq!(with(9, 0), Err(Error::UnknownAlgorithm));
+            assert_eq!(with(9, 3), Err(Error::UnknownAlgorithm));
+            assert_eq!(with(11, 1), Err(Error::InvalidKdfParams));
+            assert_eq!(with(11, 255), Err(Error::InvalidKdfParams));
+
+            let mut modified = bytes.clone();
+            modified[12..16].copy_from_slice(&0u32.to_le_bytes());
+            assert_eq!(Header::parse(&modified).map(|_| ()), Err(Error::InvalidSegmentSize));
+            modified[12..16].copy_from_slice(&(MAX_SEGMENT_SIZE + 1).to_le_bytes());
+            assert_eq!(Header::parse(&modified).map(|_| ()), Err(Error::InvalidSegmentSize));
+
+            assert_eq!(KdfParams::new(0, b""), Err(Error::InvalidKdfParams));
+            assert_eq!(KdfParams::new(1, &[0u8; 129]), Err(Error::InvalidKdfParams));
+        }
+
+        #[test]
+        fn truncated_headers_are_rejected() {
+            for header in headers() {
+                let bytes = header.encode(&KEY);
+                for len in 0..bytes.len() {
+                    assert_eq!(Header::parse(&bytes[..len]).map(|_| ()), Err(Error::Truncated), "length {}", len);
+                }
+            }
+
+            assert_eq!(Header::parse(b"C20AEAX").map(|_| ()), Err(Error::InvalidMagic));
+        }
+
+        #[test]
+        fn parse_never_panics_on_random_input() {
+            use rand::{Rng, RngCore, SeedableRng};
+
+            let mut rng = rand::rngs::StdRng::seed_from_u64(23);
+            let valid = headers()[2].encode(&KEY);
+            for _ in 0..10_000 {
+                // Keep the magic and version most of the time so the deeper fields are exercised
+                let mut bytes = valid.clone();
+                bytes.truncate(rng.gen_range(0..=valid.len()));
+                let start = if rng.gen_bool(0.9) { 9.min(bytes.len()) } else { 0 };
+                rng.fill_bytes(&mut bytes[start..]);
+                if let Ok((header, len)) = Header::parse(&bytes) {
+                    assert!(len <= bytes.len());
+                    assert!(header.verify(&KEY).is_err());
+                }
+            }
+        }
+
+        #[cfg(feature = "std")]
+        mod adapters {
+            use chacha20imp::container::{read_header, Algorithm, ContainerReader, ContainerWriter, Error, Header};
+            use std::io::{self, Read, Write};
+
+            const KEY: [u8; 32] = [0x42; 32];
+
+            fn plaintext(len: usize) -> Vec<u8> {
+                (0..len).map(|i| (i * 31 % 251) as u8).collect()
+            }
+
+            fn seal(header: &Header, data: &[u8]) -> Vec<u8> {
+                let mut writer = ContainerWriter::new(Vec::new(), &KEY, header).unwrap();
+                writer.write_all(data).unwrap();
+                writer.finish().unwrap()
+            }
+
+            fn open(container: &[u8], key: &[u8; 32]) -> io::Result<Vec<u8>> {
+                let mut plaintext = Vec::new();
+                ContainerReader::new(container, key)?.read_to_end(&mut plaintext)?;
+                Ok(plaintext)
+            }
+
+            fn error_of(error: io::Error) -> Error {
+                assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+                *error.into_inner().unwrap().downcast::<Error>().unwrap()
+            }
+
+            #[test]
+            fn round_trip() {
+                for algorithm in [Algorithm::ChaCha20Poly1305, Algorithm::XChaCha20Poly1305] {
+                    let header = Header::new(algorithm, [1; 24], [2; 19]).with_segment_size(100);
+                    for len in [0, 1, 99, 100, 101, 1000] {
+                        let data = plaintext(len);
+                        let container = seal(&header, &data);
+                        assert_eq!(container.len(), header.encoded_len() + len + 16 * len.div_ceil(100).max(1));
+                        assert_eq!(open(&container, &KEY).unwrap(), data);
+                    }
+                }
+            }
+
+            #[test]
+            fn algorithms_and_salts_give_different_ciphertexts() {
+                let data = plaintext(64);
+                let chacha = seal(&Header::new(Algorithm::ChaCha20Poly1305, [1; 24], [2; 19]), &data);
+                let xchacha = seal(&Header::new(Algorithm::XChaCha20Poly1305, [1; 24], [2; 19]), &data);
+                let salted = seal(&Header::new(Algorithm::XChaCha20Poly1305, [3; 24], [2; 19]), &data);
+                assert_ne!(chacha[chacha.len() - 80..], xchacha[xchacha.len() - 80..]);
+                assert_ne!(xchacha[xchacha.len() - 80..], salted[salted.len() - 80..]);
+            }
+
+            #[test]
+            fn wrong_key_is_reported_before_decrypting() {
+                let container = seal(&Header::new(Algorithm::XChaCha20Poly1305, [1; 24], [2; 19]), &plaintext(500));
+                let error = open(&container, &[0x43; 32]).unwrap_err();
+                assert_eq!(error_of(error), Error::WrongKey);
+
+                // The header alone is enough to detect the wrong key
+                let header_len = Header::parse(&container).unwrap().1;
+                let error = ContainerReader::new(&container[..header_len], &[0x43; 32]).err().unwrap();
+                assert_eq!(error_of(error), Error::WrongKey);
+            }
+
+            #[test]
+            fn tampering_is_rejected() {
+                let container = seal(&Header::new(Algorithm::ChaCha20Poly1305, [1; 24], [2; 19]).with_segment_size(64), &plaintext(200));
+                for i in 0..container.len() {
+                    let mut tampered = container.clone();
+                    tampered[i] ^= 0x80;
+                    assert!(open(&tampered, &KEY).is_err(), "flipped byte {}", i);
+                }
+                assert!(open(&container[..container.len() - 1], &KEY).is_err());
+            }
+
+            #[test]
+            fn read_header_consumes_only_the_header() {
+                let header = Header::new(Algorithm::XChaCha20Poly1305, [1; 24], [2; 19]);
+                let container = seal(&header, b"payload");
+
+                let mut input = &container[..];
+                let parsed = read_header(&mut input).unwrap();
+                assert_eq!(input.len(), container.len() - header.encoded_len());
+
+                let mut reader = ContainerReader::with_header(input, &KEY, parsed).unwrap();
+                let mut plaintext = Vec::new();
+                reader.read_to_end(&mut plaintext).unwrap();
+                assert_eq!(plaintext, b"payload");
+                assert_eq!(reader.header().segment_size(), header.segment_size());
+            }
+
+            #[test]
+            fn truncated_headers_are_reported() {
+                let container = seal(&Header::new(Algorithm::ChaCha20Poly1305, [1; 24], [2; 19]), b"");
+                let header_len = Header::parse(&container).unwrap().1;
+                for len in 0..header_len {
+                    let error = read_header(&mut &container[..len]).unwrap_err();
+                    assert_eq!(error_of(error), Error::Truncated, "length {}", len);
+                }
+
+                let error = read_header(&mut &b"not a container"[..]).unwrap_err();
+                assert_eq!(error_of(error), Error::InvalidMagic);
+            }
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    mod cli_tests {
+        use std::fs;
+        use std::io::Write;
+        use std::path::Path;
+        use std::process::{Command, Output, Stdio};
+
+        const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+        fn chacha20imp(args: &[&str]) -> Command {
+            let mut command = Command::new(env!("CARGO_BIN_EXE_chacha20imp"));
+            command.args(args).env_remove("CHACHA20IMP_TEST_KEY").env_remove("CHACHA20IMP_TEST_PASSWORD");
+            command
+        }
+
+        fn run(args: &[&str]) -> Output {
+            chacha20imp(args).stdin(Stdio::null()).output().unwrap()
+        }
+
+        fn run_with_stdin(command: &mut Command, stdin: &[u8]) -> Output {
+            let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+            child.stdin.take().unwrap().write_all(stdin).unwrap();
+            child.wait_with_output().unwrap()
+        }
+
+        fn path(path: &Path) -> &str {
+            path.to_str().unwrap()
+        }
+
+        fn plaintext(len: usize) -> Vec<u8> {
+            (0..len).map(|i| (i * 31 % 251) as u8).collect()
+        }
+
+        /// A temporary directory with a key file, a plaintext file and its encryption
+        fn fixture(len: usize) -> tempfile::TempDir {
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("key"), format!("{KEY_HEX}\n")).unwrap();
+            fs::write(dir.path().join("plain"), plaintext(len)).unwrap();
+
+            let output = run(&[
+                "encrypt",
+                "--key-file",
+                path(&dir.path().join("key")),
+                "-i",
+                path(&dir.path().join("plain")),
+                "-o",
+                path(&dir.path().join("enc")),
+            ]);
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            dir
+        }
+
+        fn decrypt_files(dir: &Path, key_file: &str) -> Output {
+            run(&[
+                "decrypt",
+                "--key-file",
+                path(&dir.join(key_file)),
+                "-i",
+                path(&dir.join("enc")),
+                "-o",
+                path(&dir.join("dec")),
+            ])
+        }
+
+        #[test]
+        fn files_round_trip() {
+            for len in [0, 1, 100_000] {
+                let dir = fixture(len);
+                let container = fs::read(dir.path().join("enc")).unwrap();
+                assert_eq!(&container[..8], b"C20AEAD\0");
+
+                let output = decrypt_files(dir.path(), "key");
+                assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+                assert_eq!(fs::read(dir.path().join("dec")).unwrap(), plaintext(len));
+            }
+        }
+
+        #[test]
+        fn output_files_are_private() {
+            let dir = fixture(100);
+            assert!(decrypt_files(dir.path(), "key").status.success());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                for name in ["enc", "dec"] {
+                    assert_eq!(fs::metadata(dir.path().join(name)).unwrap().permissions().mode() & 0o777, 0o600);
+                }
+            }
+        }
+
+        #[test]
+        fn the_input_is_never_overwritten() {
+            let dir = fixture(100);
+            let key_file = dir.path().join("key");
+            let plain = dir.path().join("plain");
+            let enc = dir.path().join("enc");
+            let container = fs::read(&enc).unwrap();
+
+            let output = run(&["encrypt", "--key-file", path(&key_file), "-i", path(&plain), "-o", path(&plain)]);
+            assert_eq!(output.status.code(), Some(2));
+            let output = run(&["decrypt", "--key-file", path(&key_file), "-i", path(&enc), "-o", path(&dir.path().join(".").join("enc"))]);
+            assert_eq!(output.status.code(), Some(2));
+            #[cfg(unix)]
+            {
+                let link = dir.path().join("link");
+                fs::hard_link(&enc, &link).unwrap();
+                let output = run(&["decrypt", "--key-file", path(&key_file), "-i", path(&enc), "-o", path(&link)]);
+                assert_eq!(output.status.code(), Some(2));
+            }
+
+            assert_eq!(fs::read(&plain).unwrap(), plaintext(100));
+            assert_eq!(fs::read(&enc).unwrap(), container);
+        }
+
+        #[test]
+        fn pipes_round_trip_with_key_from_environment() {
+            let data = plaintext(5000);
+            let encrypted = run_with_stdin(
+                chacha20imp(&["encrypt", "--key-env", "CHACHA20IMP_TEST_KEY", "--algorithm", "chacha20-poly1305", "--segment-size", "100"])
+                    .env("CHACHA20IMP_TEST_KEY", KEY_HEX),
+                &data,
+            );
+            assert!(encrypted.status.success());
+            // A 95-byte header, then 50 segments of 100 bytes with their tags
+            assert_eq!(encrypted.stdout.len(), 95 + data.len() + 16 * 50);
+
+            let decrypted = run_with_stdin(
+                chacha20imp(&["decrypt", "--key-env", "CHACHA20IMP_TEST_KEY", "-i", "-", "-o", "-"]).env("CHACHA20IMP_TEST_KEY", KEY_HEX),
+                &encrypted.stdout,
+            );
+            assert!(decrypted.status.success());
+            assert_eq!(decrypted.stdout, data);
+        }
+
+        #[test]
+        fn raw_key_files_are_accepted() {
+            let dir = fixture(1000);
+            let raw: Vec<u8> = (0..32).collect();
+            fs::write(dir.path().join("raw"), raw).unwrap();
+
+            let output = decrypt_files(dir.path(), "raw");
+            assert!(output.status.success());
+            assert_eq!(fs::read(dir.path().join("dec")).unwrap(), plaintext(1000));
+        }
+
+        #[test]
+        fn keygen_creates_a_usable_key_file_once() {
+            let dir = tempfile::tempdir().unwrap();
+            let key_file = dir.path().join("new.key");
+
+            let output = run(&["keygen", "-o", path(&key_file)]);
+            assert!(output.status.success());
+            let key = fs::read_to_string(&key_file).unwrap();
+            assert_eq!(key.len(), 65);
+            assert!(key.trim_end().bytes().all(|c| c.is_ascii_hexdigit()));
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                assert_eq!(fs::metadata(&key_file).unwrap().permissions().mode() & 0o777, 0o600);
+            }
+
+            let output = run(&["keygen", "-o", path(&key_file)]);
+            assert_eq!(output.status.code(), Some(1));
+            assert_eq!(fs::read_to_string(&key_file).unwrap(), key);
+
+            let output = run(&["keygen"]);
+            assert!(output.status.success());
+            assert_eq!(output.stdout.len(), 65);
+            assert_ne!(output.stdout, key.as_bytes());
+
+            let encrypted = run_with_stdin(&mut chacha20imp(&["encrypt", "--key-file", path(&key_file)]), b"secret");
+            let decrypted = run_with_stdin(&mut chacha20imp(&["decrypt", "--key-file", path(&key_file)]), &encrypted.stdout);
+            assert_eq!(decrypted.stdout, b"secret");
+        }
+
+        #[test]
+        fn wrong_key_exits_with_4_without_output() {
+            let dir = fixture(1000);
+            fs::write(dir.path().join("other"), KEY_HEX.replace('0', "f")).unwrap();
+
+            let output = decrypt_files(dir.path(), "other");
+            assert_eq!(output.status.code(), Some(4));
+            assert!(String::from_utf8_lossy(&output.stderr).contains("Wrong key"));
+            assert!(!dir.path().join("dec").exists());
+        }
+
+        #[test]
+        fn modified_input_exits_with_5_and_removes_output() {
+            let dir = fixture(200_000);
+            let mut container = fs::read(dir.path().join("enc")).unwrap();
+            let last = container.len() - 1;
+            container[last] ^= 1;
+            fs::write(dir.path().join("enc"), &container).unwrap();
+
+            let output = decrypt_files(dir.path(), "key");
+            assert_eq!(output.status.code(), Some(5));
+            assert!(!dir.path().join("dec").exists());
+
+            fs::write(dir.path().join("enc"), &container[..container.len() / 2]).unwrap();
+            assert_eq!(decrypt_files(dir.path(), "key").status.code(), Some(5));
+
+            fs::write(dir.path().join("enc"), b"plain text").unwrap();
+            assert_eq!(decrypt_files(dir.path(), "key").status.code(), Some(5));
+        }
+
+        #[test]
+        fn key_errors_exit_with_3() {
+            let dir = fixture(10);
+            fs::write(dir.path().join("short"), &KEY_HEX[..62]).unwrap();
+            assert_eq!(decrypt_files(dir.path(), "short").status.code(), Some(3));
+            assert_eq!(decrypt_files(dir.path(), "missing").status.code(), Some(3));
+
+            let output = run(&["decrypt", "--key-env", "CHACHA20IMP_TEST_KEY", "-i", path(&dir.path().join("enc"))]);
+            assert_eq!(output.status.code(), Some(3));
+            let output = chacha20imp(&["decrypt", "--key-env", "CHACHA20IMP_TEST_KEY", "-i", path(&dir.path().join("enc"))])
+                .env("CHACHA20IMP_TEST_KEY", "not hex")
+                .output()
+                .unwrap();
+            assert_eq!(output.status.code(), Some(3));
+        }
+
+        #[test]
+        fn io_and_usage_errors() {
+            let dir = fixture(10);
+            let key_file = dir.path().join("key");
+            let missing = dir.path().join("missing");
+
+            let output = run(&["encrypt", "--key-file", path(&key_file), "-i", path(&missing)]);
+            assert_eq!(output.status.code(), Some(1));
+            assert!(String::from_utf8_lossy(&output.stderr).contains(path(&missing)));
+
+            assert_eq!(run(&["encrypt"]).status.code(), Some(2));
+            assert_eq!(run(&["encrypt", "--key-file", path(&key_file), "--key-env", "X"]).status.code(), Some(2));
+            assert_eq!(run(&["encrypt", "--key-file", path(&key_file), "--segment-size", "0"]).status.code(), Some(2));
+            assert_eq!(run(&["frobnicate"]).status.code(), Some(2));
+        }
+
+        fn encrypt_with_password(dir: &Path, args: &[&str]) -> Output {
+            let mut command = chacha20imp(&["encrypt", "--password-env", "CHACHA20IMP_TEST_PASSWORD", "-i", path(&dir.join("plain")), "-o", path(&dir.join("enc"))]);
+            command.args(args).env("CHACHA20IMP_TEST_PASSWORD", "correct horse").output().unwrap()
+        }
+
+        fn decrypt_with_password(dir: &Path, password: &str) -> Output {
+            chacha20imp(&["decrypt", "--password-env", "CHACHA20IMP_TEST_PASSWORD", "-i", path(&dir.join("enc")), "-o", path(&dir.join("dec"))])
+                .env("CHACHA20IMP_TEST_PASSWORD", password)
+                .output()
+                .unwrap()
+        }
+
+        #[test]
+        fn passwords_round_trip_with_parameters_from_the_header() {
+            use chacha20imp::container::Header;
+            use chacha20imp::kdf::{Argon2Params, Kdf, ScryptParams};
+
+            let cases = [
+                (&["--kdf-params", "m=64,t=3,p=2"][..], Kdf::Argon2id(Argon2Params::new(64, 3, 2).unwrap())),
+                (&["--kdf", "scrypt", "--kdf-params", "ln=6,r=4"][..], Kdf::Scrypt(ScryptParams::new(6, 4, 1).unwrap())),
+            ];
+            for (args, expected) in cases {
+                let dir = fixture(1000);
+                let output = encrypt_with_password(dir.path(), args);
+                assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+                let container = fs::read(dir.path().join("enc")).unwrap();
+                let (header, _) = Header::parse(&container).unwrap();
+                assert_eq!(Kdf::try_from(header.kdf().unwrap()), Ok(expected));
+
+                let output = decrypt_with_password(dir.path(), "correct horse");
+                assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+                assert_eq!(fs::read(dir.path().join("dec")).unwrap(), plaintext(1000));
+            }
+        }
+
+        #[test]
+        fn wrong_password_exits_with_4() {
+            let dir = fixture(1000);
+            assert!(encrypt_with_password(dir.path(), &["--kdf-params", "m=64,t=1"]).status.success());
+
+            let output = decrypt_with_password(dir.path(), "correct horse!");
+            assert_eq!(output.status.code(), Some(4));
+            assert!(!dir.path().join("dec").exists());
+
+            // A key file does not open a password container either
+            assert_eq!(decrypt_files(dir.path(), "key").status.code(), Some(4));
+        }
+
+        #[test]
+        fn password_errors() {
+            let dir = fixture(10);
+            // The fixture was encrypted with a key, which has no KDF parameters
+            assert_eq!(decrypt_with_password(dir.path(), "correct horse").status.code(), Some(3));
+            assert_eq!(decrypt_with_password(dir.path(), "").status.code(), Some(3));
+
+            assert_eq!(encrypt_with_password(dir.path(), &["--kdf-params", "m=7"]).status.code(), Some(2));
+            assert_eq!(encrypt_with_password(dir.path(), &["--kdf-params", "ln=10"]).status.code(), Some(2));
+            assert_eq!(encrypt_with_password(dir.path(), &["--kdf", "scrypt", "--kdf-params", "ln=300"]).status.code(), Some(2));
+            assert_eq!(encrypt_with_password(dir.path(), &["--kdf-params", "m"]).status.code(), Some(2));
+            // Within range for Argon2id, but above what decryption accepts from a header
+            assert_eq!(encrypt_with_password(dir.path(), &["--kdf-params", "t=11"]).status.code(), Some(2));
+            assert_eq!(run(&["decrypt", "--password", "--key-env", "X"]).status.code(), Some(2));
+        }
+    }
+
+    #[cfg(feature = "kdf")]
+    mod kdf_tests {
+        use chacha20imp::container::{Algorithm, Header, KdfParams};
+        use chacha20imp::kdf::{
+            derive_key, Argon2Params, Error, Kdf, Limits, ScryptParams, DEFAULT_MAX_ITERATIONS, DEFAULT_MAX_MEMORY_KIB,
+            DEFAULT_MAX_PARALLELISM, MAX_MEMORY_KIB,
+        };
+        use hex_literal::hex;
+
+        /// RFC 9106, section 5.3
+        #[test]
+        fn argon2id_rfc9106_test_vector() {
+            let params = Argon2Params::new(32, 3, 4).unwrap();
+            let key = params.derive_key_with_secret(&[0x01; 32], &[0x02; 16], &[0x03; 8], &[0x04; 12]).unwrap();
+            assert_eq!(key.as_bytes(), &hex!("0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"));
+        }
+
+        /// RFC 7914, section 12, truncated to the 32-byte key: the scrypt output is
+        /// PBKDF2-HMAC-SHA256, so the first 32 bytes do not depend on the requested length
+        #[test]
+        fn scrypt_rfc7914_test_vectors() {
+            let check = |password: &[u8], salt: &[u8], log_n: u8, r: u32, p: u32, expected: [u8; 32]| {
+                let kdf = Kdf::Scrypt(ScryptParams::new(log_n, r, p).unwrap());
+                assert_eq!(derive_key(password, salt, &kdf).unwrap().as_bytes(), &expected);
+            };
+
+            check(b"", b"", 4, 1, 1, hex!("77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442"));
+            check(b"password", b"NaCl", 10, 8, 16, hex!("fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162"));
+            check(
+                b"pleaseletmein",
+                b"SodiumChloride",
+                14,
+                8,
+                1,
+                hex!("7023bdcb3afd7348461c06cd81fd38ebfda8fbba904f8e3ea9b543f6545da1f2"),
+            );
+        }
+
+        #[test]
+        fn inputs_change_the_key() {
+            let argon2 = Argon2Params::new(64, 1, 1).unwrap();
+            let key = argon2.derive_key(b"password", b"saltsalt").unwrap();
+            assert_ne!(argon2.derive_key(b"passwore", b"saltsalt").unwrap().as_bytes(), key.as_bytes());
+            assert_ne!(argon2.derive_key(b"password", b"saltsalu").unwrap().as_bytes(), key.as_bytes());
+            let other = Argon2Params::new(64, 2, 1).unwrap();
+            assert_ne!(other.derive_key(b"password", b"saltsalt").unwrap().as_bytes(), key.as_bytes());
+
+            let scrypt = Kdf::Scrypt(ScryptParams::new(4, 1, 1).unwrap());
+            assert_ne!(derive_key(b"password", b"saltsalt", &scrypt).unwrap().as_bytes(), key.as_bytes());
+            assert_eq!(format!("{:?}", key), "Key(..)");
+        }
+
+        #[test]
+        fn invalid_params_are_rejected() {
+            assert_eq!(Argon2Params::new(7, 1, 1), Err(Error::InvalidParams));
+            assert_eq!(Argon2Params::new(64, 0, 1), Err(Error::InvalidParams));
+            assert_eq!(Argon2Params::new(64, 1, 0), Err(Error::InvalidParams));
+            assert_eq!(Argon2Params::new(64, 1, 9), Err(Error::InvalidParams));
+            assert_eq!(Argon2Params::new(MAX_MEMORY_KIB + 1, 1, 1), Err(Error::InvalidParams));
+            assert!(Argon2Params::new(MAX_MEMORY_KIB, 1, 1).is_ok());
+
+            assert_eq!(ScryptParams::new(4, 0, 1), Err(Error::InvalidParams));
+            assert_eq!(ScryptParams::new(4, 1, 0), Err(Error::InvalidParams));
+            assert_eq!(ScryptParams::new(16, 1, 1), Err(Error::InvalidParams));
+            assert_eq!(ScryptParams::new(23, 8, 1), Err(Error::InvalidParams));
+            assert_eq!(ScryptParams::new(64, 8, 1), Err(Error::InvalidParams));
+            assert_eq!(ScryptParams::new(255, u32::MAX, 1), Err(Error::InvalidParams));
+            assert!(ScryptParams::new(22, 8, 1).is_ok());
+
+            let argon2 = Argon2Params::new(64, 1, 1).unwrap();
+            assert_eq!(argon2.derive_key(b"password", b"short").unwrap_err(), Error::SaltTooShort);
+            assert_eq!(argon2.derive_key_with_secret(b"password", b"saltsalt", b"", &[0; 33]).unwrap_err(), Error::InvalidParams);
+        }
+
+        #[test]
+        fn header_params_round_trip() {
+            for kdf in [
+                Kdf::default(),
+                Kdf::Argon2id(Argon2Params::new(1 << 20, 4, 8).unwrap()),
+                Kdf::Scrypt(ScryptParams::default()),
+                Kdf::Scrypt(ScryptParams::new(4, 1, 3).unwrap()),
+            ] {
+                let params = KdfParams::from(kdf);
+                assert_eq!(Kdf::try_from(&params), Ok(kdf));
+
+                let header = Header::new(Algorithm::XChaCha20Poly1305, [1; 24], [2; 19]).with_kdf(params);
+                let (parsed, _) = Header::parse(&header.encode(&[0; 32])).unwrap();
+                assert_eq!(Kdf::try_from(parsed.kdf().unwrap()), Ok(kdf));
+            }
+
+            assert_eq!(KdfParams::from(Kdf::default()).params(), &hex!("004c0000 02000000 01000000"));
+            assert_eq!(KdfParams::from(Kdf::Scrypt(ScryptParams::default())).params(), &hex!("11 08000000 01000000"));
+        }
+
+        #[test]
+        fn malformed_header_params_are_rejected() {
+            let kdf = |id: u8, params: &[u8]| Kdf::try_from(&KdfParams::new(id, params).unwrap());
+
+            assert_eq!(kdf(3, &[]), Err(Error::UnknownKdf));
+            assert_eq!(kdf(1, &[0; 11]), Err(Error::InvalidParams));
+            assert_eq!(kdf(2, &[0; 10]), Err(Error::InvalidParams));
+            // 8 GiB of Argon2 memory and 2^40 scrypt blocks
+            assert_eq!(kdf(1, &hex!("00008000 01000000 01000000")), Err(Error::InvalidParams));
+            assert_eq!(kdf(2, &hex!("28 08000000 01000000")), Err(Error::InvalidParams));
+            assert_eq!(kdf(1, &hex!("40000000 00000000 01000000")), Err(Error::InvalidParams));
+        }
+
+        #[test]
+        fn header_params_above_the_limits_are_rejected() {
+            let params = |id: u8, params: &[u8]| KdfParams::new(id, params).unwrap();
+
+            // Argon2id with u32::MAX passes, 64 lanes and 2 GiB, then scrypt with p = 2^20 and 2 GiB
+            let costly = [
+                params(1, &hex!("00020000 ffffffff 01000000")),
+                params(1, &hex!("00020000 01000000 40000000")),
+                params(1, &hex!("00002000 01000000 01000000")),
+                params(2, &hex!("04 01000000 00001000")),
+                params(2, &hex!("15 08000000 01000000")),
+            ];
+            for params in &costly {
+                assert_eq!(Kdf::try_from(params), Err(Error::InvalidParams), "{params:?}");
+            }
+
+            let generous = Limits::new(MAX_MEMORY_KIB, u32::MAX, 1 << 20);
+            for params in &costly {
+                assert!(Kdf::from_params(params, &generous).is_ok(), "{params:?}");
+            }
+
+            // The defaults and the limits themselves are accepted
+            let defaults = [Kdf::default(), Kdf::Scrypt(ScryptParams::default())];
+            for kdf in defaults {
+                assert_eq!(Kdf::try_from(&KdfParams::from(kdf)), Ok(kdf));
+            }
+            let kdf = Kdf::Argon2id(Argon2Params::new(DEFAULT_MAX_MEMORY_KIB, DEFAULT_MAX_ITERATIONS, DEFAULT_MAX_PARALLELISM).unwrap());
+            assert_eq!(Kdf::try_from(&KdfParams::from(kdf)), Ok(kdf));
+            let kdf = Kdf::Argon2id(Argon2Params::new(64, DEFAULT_MAX_ITERATIONS + 1, 1).unwrap());
+            assert_eq!(Limits::default().check(&kdf), Err(Error::InvalidParams));
+        }
+
+        #[test]
+        fn derived_key_opens_a_container() {
+            let kdf = Kdf::Scrypt(ScryptParams::new(8, 8, 1).unwrap());
+            let header = Header::new(Algorithm::ChaCha20Poly1305, [7; 24], [8; 19]).with_kdf(kdf.into());
+            let bytes = header.encode(derive_key(b"hunter2", header.salt(), &kdf).unwrap().as_bytes());
+
+            let (parsed, _) = Header::parse(&bytes).unwrap();
+            let kdf = Kdf::try_from(parsed.kdf().unwrap()).unwrap();
+            let key = derive_key(b"hunter2", parsed.salt(), &kdf).unwrap();
+            assert_eq!(parsed.verify(key.as_bytes()), Ok(()));
+
+            let key = derive_key(b"hunter3", parsed.salt(), &kdf).unwrap();
+            assert_eq!(parsed.verify(key.as_bytes()), Err(chacha20imp::container::Error::WrongKey));
+        }
+
+        #[cfg(feature = "rand_core")]
+        #[test]
+        fn generated_salts_are_random() {
+            use chacha20imp::kdf::generate_salt;
+            use chacha20imp::rand_core::SeedableRng;
+            use chacha20imp::rng::ChaChaRng;
+
+            let mut rng = ChaChaRng::seed_from_u64(25);
+            let salt = generate_salt(&mut rng);
+            assert_ne!(salt, [0; 24]);
+            assert_ne!(generate_salt(&mut rng), salt);
         }
     }
 }